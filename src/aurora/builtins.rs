@@ -0,0 +1,208 @@
+use std::{cell::RefCell, rc::Rc};
+
+use chrono::prelude::*;
+
+use super::{
+    environment::Environment,
+    gc,
+    expressions::{FunctionType, InternalFunction, Object},
+    scheduler::{ProcessResult, Wait},
+    token::Token,
+};
+
+fn arity(internaltype: &InternalFunction) -> usize {
+    match internaltype {
+        InternalFunction::Time
+        | InternalFunction::Clock
+        | InternalFunction::Now
+        | InternalFunction::GcStats => 0,
+        InternalFunction::Spawn | InternalFunction::Sleep | InternalFunction::Exec => 1,
+        InternalFunction::Len
+        | InternalFunction::Type
+        | InternalFunction::Fields
+        | InternalFunction::Methods
+        | InternalFunction::ClassOf => 1,
+        InternalFunction::HasMethod => 2,
+    }
+}
+
+pub fn call_internal(
+    env: &mut Environment,
+    internaltype: InternalFunction,
+    arguments: Vec<Object>,
+    paren: &Token,
+) -> Object {
+    if arguments.len() != arity(&internaltype) {
+        panic!(
+            "Wrong Number of arguments for function {:?} at {}",
+            internaltype, paren.line
+        );
+    }
+    match internaltype {
+        InternalFunction::Time => return Object::StringObject(Local::now().to_string()),
+        InternalFunction::Clock => return Object::NumberObject(Local::now().timestamp() as f64),
+        InternalFunction::Len => match &arguments[0] {
+            Object::StringObject(s) => return Object::NumberObject(s.chars().count() as f64),
+            Object::ListObject(l) => return Object::NumberObject(l.len() as f64),
+            other => panic!("len() expects a string or list, got {:?}", other),
+        },
+        InternalFunction::Type => {
+            return Object::StringObject(type_name(&arguments[0]).to_owned())
+        }
+        InternalFunction::Fields => match &arguments[0] {
+            Object::ClassInstance { memory, .. } => return names_list(memory.borrow().names()),
+            other => panic!("fields() expects an instance, got {:?}", other),
+        },
+        InternalFunction::Methods => match &arguments[0] {
+            Object::Class { .. } => return names_list(method_names(&arguments[0])),
+            Object::ClassInstance { class, .. } => return names_list(method_names(class)),
+            other => panic!("methods() expects a class or instance, got {:?}", other),
+        },
+        InternalFunction::ClassOf => match &arguments[0] {
+            Object::ClassInstance { class, .. } => return (**class).clone(),
+            other => panic!("class_of() expects an instance, got {:?}", other),
+        },
+        InternalFunction::HasMethod => {
+            let name = match &arguments[1] {
+                Object::StringObject(s) => s.clone(),
+                other => panic!("has_method() expects a method name, got {:?}", other),
+            };
+            let class = match &arguments[0] {
+                Object::ClassInstance { class, .. } => (**class).clone(),
+                other => other.clone(),
+            };
+            return Object::BoolObject(method_names(&class).contains(&name));
+        }
+        InternalFunction::Spawn => match &arguments[0] {
+            Object::Generator(generator) => return env.spawn(generator.clone()),
+            other => panic!("spawn() expects a generator, got {:?}", other),
+        },
+        InternalFunction::Sleep => match &arguments[0] {
+            Object::NumberObject(ms) => return Object::Wait(Wait::Sleep(*ms)),
+            other => panic!("sleep() expects milliseconds, got {:?}", other),
+        },
+        InternalFunction::Exec => match &arguments[0] {
+            Object::StringObject(command) => {
+                return Object::Wait(Wait::Process {
+                    command: command.clone(),
+                    result: Rc::new(RefCell::new(ProcessResult::default())),
+                })
+            }
+            other => panic!("exec() expects a command string, got {:?}", other),
+        },
+        InternalFunction::Now => return Object::NumberObject(env.clock()),
+        InternalFunction::GcStats => {
+            gc::collect(env);
+            return Object::ListObject(
+                gc::stats()
+                    .into_iter()
+                    .map(|(name, value)| {
+                        Object::ListObject(vec![
+                            Object::StringObject(name.to_owned()),
+                            Object::NumberObject(value as f64),
+                        ])
+                    })
+                    .collect(),
+            );
+        }
+    }
+}
+
+pub fn type_name(value: &Object) -> &'static str {
+    match value {
+        Object::StringObject(_) => "string",
+        Object::NumberObject(_) => "number",
+        Object::BoolObject(_) => "bool",
+        Object::ListObject(_) => "list",
+        Object::InternalFunction { .. }
+        | Object::FunctionObject { .. }
+        | Object::BoundMethod { .. }
+        | Object::EnumConstructor(_)
+        | Object::GeneratorMethod { .. } => "function",
+        Object::Generator(_) => "generator",
+        Object::Task(_) => "task",
+        Object::Wait(_) => "wait",
+        Object::Enum { .. } => "enum",
+        Object::EnumValue(_) => "variant",
+        Object::Class { .. } => "class",
+        Object::Trait { .. } => "trait",
+        Object::ClassInstance { .. } => "instance",
+        Object::NilObject => "nil",
+    }
+}
+
+// `value is Class` holds when the instance's class or any of its
+// superclasses is `class`; `value is Enum` when it is one of its variants.
+// Classes are compared by identity, so a class shadowing another of the same
+// name is a different class.
+pub fn is_instance_of(value: &Object, class: &Object, op: &Token) -> bool {
+    let target = match class {
+        Object::Class { class_env, .. } => class_env,
+        Object::Enum { name, .. } => match value {
+            Object::EnumValue(v) => return v.enum_name == name.lexeme,
+            _ => return false,
+        },
+        Object::Trait { name, .. } => panic!(
+            "Right side of {} must be a class or an enum, {} is a trait at line {}",
+            op.lexeme, name.lexeme, op.line
+        ),
+        other => panic!("Right side of {} must be a class, got {:?}", op.lexeme, other),
+    };
+    let mut current = match value {
+        Object::ClassInstance { class, .. } => Some(class.clone()),
+        _ => None,
+    };
+    while let Some(c) = current {
+        match &*c {
+            Object::Class {
+                class_env,
+                superclass,
+                ..
+            } => {
+                if Rc::ptr_eq(class_env, target) {
+                    return true;
+                }
+                current = superclass.clone();
+            }
+            _ => return false,
+        }
+    }
+    return false;
+}
+
+// Names of the methods callable on instances of `class`, inherited ones
+// included. Constructors, accessors and static members are left out.
+fn method_names(class: &Object) -> Vec<String> {
+    let mut names = Vec::<String>::new();
+    let mut current = Some(class);
+    while let Some(c) = current {
+        match c {
+            Object::Class {
+                class_env,
+                superclass,
+                ..
+            } => {
+                for name in class_env.names() {
+                    if names.contains(&name) {
+                        continue;
+                    }
+                    if let Some(Object::FunctionObject {
+                        functype: FunctionType::Method | FunctionType::Generator,
+                        ..
+                    }) = Token::lookup(&name).and_then(|token| class_env.find(token))
+                    {
+                        names.push(name);
+                    }
+                }
+                current = superclass.as_deref();
+            }
+            other => panic!("Expected a class, got {:?}", other),
+        }
+    }
+    names.sort();
+    return names;
+}
+
+fn names_list(names: Vec<String>) -> Object {
+    return Object::ListObject(names.into_iter().map(Object::StringObject).collect());
+}
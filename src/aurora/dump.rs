@@ -0,0 +1,347 @@
+use std::fmt::Write;
+
+use super::{
+    expressions::{Expression, FunctionType, Object},
+    patterns::Pattern,
+    statements::Statement,
+    token::Token,
+};
+
+// Text forms of the scanner and parser output for `--dump-tokens` and
+// `--dump-ast`. Both leave out anything that changes between runs or
+// builds (addresses, caches, spans), so dumps can be compared against
+// golden files.
+
+// One token per line: source line, token type, quoted lexeme and, for
+// string and number literals, the literal value.
+pub fn tokens(tokens: &[Token]) -> String {
+    let mut out = String::new();
+    for token in tokens {
+        let _ = write!(
+            out,
+            "{:>4} {:<14} {:?}",
+            token.line,
+            token.tokentype.to_string(),
+            token.lexeme.as_str()
+        );
+        if !token.literal.as_str().is_empty() {
+            let _ = write!(out, " {:?}", token.literal.as_str());
+        }
+        out.push('\n');
+    }
+    return out;
+}
+
+// The program as S-expressions. Statements that contain other statements
+// put each of them on its own line, indented by two spaces; expressions are
+// written on a single line.
+pub fn ast(statements: &[Statement]) -> String {
+    let mut out = String::new();
+    for stmnt in statements {
+        statement(&mut out, stmnt, 0);
+    }
+    return out;
+}
+
+fn line(out: &mut String, depth: usize, text: &str) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+    out.push_str(text);
+    out.push('\n');
+}
+
+// Writes `head`, the statements nested in it one level deeper, then closes
+// the parenthesis on the last of them.
+fn node(out: &mut String, depth: usize, head: &str, children: &[&Statement]) {
+    line(out, depth, &format!("({head}"));
+    for child in children {
+        statement(out, child, depth + 1);
+    }
+    out.pop();
+    out.push_str(")\n");
+}
+
+fn statement(out: &mut String, stmnt: &Statement, depth: usize) {
+    match stmnt {
+        Statement::Block { statements } => {
+            let children: Vec<&Statement> = statements.iter().collect();
+            node(out, depth, "block", &children);
+        }
+        Statement::Class {
+            name,
+            superclass,
+            traits,
+            methods,
+            statics,
+        } => {
+            let mut head = format!("class {}", name.lexeme);
+            if let Some(s) = superclass {
+                let _ = write!(head, " < {}", s.lexeme);
+            }
+            if !traits.is_empty() {
+                let _ = write!(head, " (with {})", names(traits));
+            }
+            line(out, depth, &format!("({head}"));
+            for method in methods {
+                statement(out, method, depth + 1);
+            }
+            for method in statics {
+                node(out, depth + 1, "static", &[method]);
+            }
+            out.pop();
+            out.push_str(")\n");
+        }
+        Statement::Trait {
+            name,
+            required,
+            methods,
+        } => {
+            let mut head = format!("trait {}", name.lexeme);
+            if !required.is_empty() {
+                let _ = write!(head, " (requires {})", names(required));
+            }
+            let children: Vec<&Statement> = methods.iter().collect();
+            node(out, depth, &head, &children);
+        }
+        Statement::Enum { name, variants } => {
+            let mut text = format!("(enum {}", name.lexeme);
+            for (tag, fields) in variants.iter() {
+                let _ = write!(text, " ({}", tag.lexeme);
+                if !fields.is_empty() {
+                    let _ = write!(text, " {}", names(fields));
+                }
+                text.push(')');
+            }
+            text.push(')');
+            line(out, depth, &text);
+        }
+        Statement::Expression { expression: e } => {
+            line(out, depth, &format!("(expr {})", expression(e)))
+        }
+        Statement::Function {
+            name,
+            params,
+            body,
+            functype,
+        } => {
+            let kind = match functype {
+                FunctionType::Function => "function",
+                FunctionType::Method => "method",
+                FunctionType::Constructor => "constructor",
+                FunctionType::Getter => "getter",
+                FunctionType::Setter => "setter",
+                FunctionType::Generator => "generator",
+            };
+            let head = format!("{kind} {} ({})", name.lexeme, names(params));
+            node(out, depth, &head, &[&**body]);
+        }
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let head = format!("if {}", expression(condition));
+            match else_branch {
+                Some(b) => node(out, depth, &head, &[&**then_branch, &**b]),
+                None => node(out, depth, &head, &[&**then_branch]),
+            }
+        }
+        Statement::Print { expression: e } => {
+            line(out, depth, &format!("(print {})", expression(e)))
+        }
+        Statement::Return { value, .. } => line(out, depth, &optional("return", value.as_ref())),
+        Statement::Yield { value, .. } => line(out, depth, &optional("yield", value.as_ref())),
+        Statement::Variable { name, init } => line(
+            out,
+            depth,
+            &optional(&format!("var {}", name.lexeme), init.as_ref()),
+        ),
+        Statement::Constant { name, init } => line(
+            out,
+            depth,
+            &format!("(const {} {})", name.lexeme, expression(init)),
+        ),
+        Statement::Destructure {
+            pattern: p, init, ..
+        } => line(
+            out,
+            depth,
+            &format!("(var {} {})", pattern(p), expression(init)),
+        ),
+        Statement::While { condition, body } => {
+            let head = format!("while {}", expression(condition));
+            node(out, depth, &head, &[&**body]);
+        }
+        Statement::For {
+            init,
+            condition,
+            increment,
+            body,
+        } => {
+            // Missing clauses are written as `_` so the parts keep their
+            // positions.
+            let head = format!(
+                "for {} {}",
+                condition.as_ref().map_or("_".to_owned(), expression),
+                increment.as_ref().map_or("_".to_owned(), expression),
+            );
+            match &**init {
+                Some(i) => node(out, depth, &head, &[i, &**body]),
+                None => {
+                    line(out, depth, &format!("({head}"));
+                    line(out, depth + 1, "_");
+                    statement(out, body, depth + 1);
+                    out.pop();
+                    out.push_str(")\n");
+                }
+            }
+        }
+        Statement::ForIn {
+            pattern: p,
+            iterable,
+            body,
+            ..
+        } => {
+            let head = format!("for-in {} {}", pattern(p), expression(iterable));
+            node(out, depth, &head, &[&**body]);
+        }
+    }
+}
+
+fn optional(head: &str, value: Option<&Expression>) -> String {
+    match value {
+        Some(e) => return format!("({head} {})", expression(e)),
+        None => return format!("({head})"),
+    }
+}
+
+fn names(tokens: &[Token]) -> String {
+    let names: Vec<&str> = tokens.iter().map(|t| t.lexeme.as_str()).collect();
+    return names.join(" ");
+}
+
+fn list(head: &str, items: impl Iterator<Item = String>) -> String {
+    let mut text = format!("({head}");
+    for item in items {
+        text.push(' ');
+        text.push_str(&item);
+    }
+    text.push(')');
+    return text;
+}
+
+fn literal(value: &Object) -> String {
+    match value {
+        Object::NumberObject(n) => return format!("{n}"),
+        Object::StringObject(s) => return format!("{s:?}"),
+        Object::BoolObject(b) => return format!("{b}"),
+        Object::NilObject => return "nil".to_owned(),
+        other => return format!("{other:?}"),
+    }
+}
+
+fn expression(expr: &Expression) -> String {
+    match expr {
+        Expression::Assign { name, value } => {
+            return format!("(= {} {})", name.lexeme, expression(value))
+        }
+        Expression::AssignPattern {
+            pattern: p, value, ..
+        } => return format!("(= {} {})", pattern(p), expression(value)),
+        Expression::Binary {
+            left,
+            operator,
+            right,
+        }
+        | Expression::Logical {
+            left,
+            operator,
+            right,
+        } => {
+            return format!(
+                "({} {} {})",
+                operator.lexeme,
+                expression(left),
+                expression(right)
+            )
+        }
+        Expression::Call {
+            callee, arguments, ..
+        } => {
+            return list(
+                "call",
+                std::iter::once(expression(callee)).chain(arguments.iter().map(expression)),
+            )
+        }
+        Expression::Get { object, name, .. } => {
+            return format!("(get {} {})", expression(object), name.lexeme)
+        }
+        Expression::Set {
+            object,
+            name,
+            value,
+            ..
+        } => {
+            return format!(
+                "(set {} {} {})",
+                expression(object),
+                name.lexeme,
+                expression(value)
+            )
+        }
+        Expression::Grouping { expression: e } => return format!("(group {})", expression(e)),
+        Expression::Index { object, index, .. } => {
+            return format!("(index {} {})", expression(object), expression(index))
+        }
+        Expression::List { elements } => return list("list", elements.iter().map(expression)),
+        Expression::Match { subject, arms, .. } => {
+            let arms = arms.iter().map(|arm| {
+                let mut text = format!("(arm {}", pattern(&arm.pattern));
+                if let Some(guard) = &arm.guard {
+                    let _ = write!(text, " (if {})", expression(guard));
+                }
+                let _ = write!(text, " {})", expression(&arm.body));
+                return text;
+            });
+            return list("match", std::iter::once(expression(subject)).chain(arms));
+        }
+        Expression::Literal { value } => return literal(value),
+        Expression::Super { method, .. } => return format!("(super {})", method.lexeme),
+        Expression::This { .. } => return "this".to_owned(),
+        Expression::Unary { operator, right } => {
+            return format!("({} {})", operator.lexeme, expression(right))
+        }
+        Expression::Variable { name } => return name.lexeme.to_string(),
+    }
+}
+
+fn pattern(p: &Pattern) -> String {
+    match p {
+        Pattern::Wildcard => return "_".to_owned(),
+        Pattern::Literal(value) => return literal(value),
+        Pattern::Binding(name) => return name.lexeme.to_string(),
+        Pattern::List { elements, rest } => {
+            let mut items: Vec<String> = elements.iter().map(pattern).collect();
+            if let Some(r) = rest {
+                items.push(format!("...{}", pattern(r)));
+            }
+            return format!("[{}]", items.join(" "));
+        }
+        Pattern::Map(entries) => {
+            let items: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key.lexeme, pattern(value)))
+                .collect();
+            return format!("{{{}}}", items.join(", "));
+        }
+        Pattern::Variant {
+            enum_name,
+            tag,
+            fields,
+        } => {
+            let fields: Vec<String> = fields.iter().map(pattern).collect();
+            return format!("{}.{}({})", enum_name.lexeme, tag.lexeme, fields.join(" "));
+        }
+    }
+}
@@ -1,108 +1,259 @@
-use std::collections::BTreeMap;
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use super::{
     expressions::{FunctionType, Object, InternalFunction},
-    token::{Token, TokenType},
+    gc,
+    generators::Generator,
+    interpreter::RuntimeError,
+    interner::Symbol,
+    scheduler::Task,
+    token::Token,
 };
 
+// Deep enough for ordinary recursion. Debug builds use several times more
+// native stack per call, so they stop sooner.
+pub const DEFAULT_MAX_DEPTH: usize = if cfg!(debug_assertions) { 25_000 } else { 100_000 };
+
+// Native stack to reserve per call allowed, with room for calls made from
+// deeply nested expressions. `main.rs` sizes the interpreter's thread from
+// this and the depth limit, so reaching the limit raises StackOverflow
+// rather than overflowing the real stack.
+pub const STACK_PER_CALL: usize = if cfg!(debug_assertions) { 64 * 1024 } else { 16 * 1024 };
+
+// How many of the innermost calls a stack overflow error reports.
+const REPORTED_FRAMES: usize = 10;
+
+// A scope stores its values in slots, in the order they were defined. Inside
+// an `Environment` names are found through its binding table; a scope on its
+// own (an instance or a class's statics) is small and searched directly.
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct Memory {
-    stack: BTreeMap<String, Object>,
+    names: Vec<Symbol>,
+    slots: Vec<Object>,
+    constants: Vec<bool>,
 }
 
 impl Memory {
     pub fn new() -> Self {
         return Memory {
-            stack: BTreeMap::<String, Object>::new(),
+            names: Vec::<Symbol>::new(),
+            slots: Vec::<Object>::new(),
+            constants: Vec::<bool>::new(),
         };
     }
 
+    fn slot(&self, name: Symbol) -> Option<usize> {
+        return self.names.iter().position(|n| *n == name);
+    }
+
+    fn push(&mut self, k: Token, v: Object) -> usize {
+        self.names.push(k.lexeme);
+        self.slots.push(v);
+        self.constants.push(false);
+        return self.slots.len() - 1;
+    }
+
+    fn set(&mut self, slot: usize, k: Token, v: Object) {
+        if self.constants[slot] {
+            panic!("Cannot redeclare constant {} at {}", k.lexeme, k.line);
+        }
+        self.slots[slot] = v;
+    }
+
+    fn assign_slot(&mut self, slot: usize, token: Token, value: Object) {
+        if self.constants[slot] {
+            panic!("Cannot assign to constant {} at {}", token.lexeme, token.line);
+        }
+        self.slots[slot] = value;
+    }
+
     pub fn define(&mut self, k: Token, v: Object) {
-        self.stack.insert(k.lexeme.clone(), v);
+        match self.slot(k.lexeme) {
+            Some(slot) => self.set(slot, k, v),
+            None => {
+                self.push(k, v);
+            }
+        }
     }
 
     pub fn get(&self, token: Token) -> Option<Object> {
-        match self.stack.get(&token.lexeme) {
-            Some(x) => return Some(x.clone()),
+        match self.slot(token.lexeme) {
+            Some(slot) => return Some(self.slots[slot].clone()),
             None => None,
         }
     }
 
-    pub fn assign(&mut self, token: Token, value: Object) -> Result<(), ()> {
-        match self.stack.get(&token.lexeme) {
-            Some(_) => {
-                self.stack.insert(token.lexeme.clone(), value);
-                return Ok(());
+    // Like `get`, trying slot `hint` before searching. Also returns the slot
+    // the value was found in.
+    pub fn get_hinted(&self, token: Token, hint: usize) -> Option<(usize, Object)> {
+        let slot = match self.names.get(hint) {
+            Some(name) if *name == token.lexeme => hint,
+            _ => self.slot(token.lexeme)?,
+        };
+        return Some((slot, self.slots[slot].clone()));
+    }
+
+    // Like `define`, trying slot `hint` first. Returns the slot used.
+    pub fn define_hinted(&mut self, k: Token, v: Object, hint: usize) -> usize {
+        let slot = match self.names.get(hint) {
+            Some(name) if *name == k.lexeme => Some(hint),
+            _ => self.slot(k.lexeme),
+        };
+        match slot {
+            Some(slot) => {
+                self.set(slot, k, v);
+                return slot;
             }
-            None => Err(()),
+            None => return self.push(k, v),
         }
     }
+
+    // Slots are in definition order, so sort to keep listings alphabetical.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.names.iter().map(|k| k.to_string()).collect();
+        names.sort();
+        return names;
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Object> {
+        return self.slots.iter();
+    }
+}
+
+// The scope a function was defined in. The id tells whether the scope at
+// `index` is still that one when the function runs, see `enter_function`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct ScopeRef {
+    index: usize,
+    id: u64,
+}
+
+// Ids are unique across environments, so a scope of a class's own
+// environment never matches one of the interpreter's.
+static NEXT_SCOPE_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_scope_id() -> u64 {
+    return NEXT_SCOPE_ID.fetch_add(1, Ordering::Relaxed);
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct Environment {
     stack: Vec<Memory>,
+    // Unique id of each scope on the stack.
+    scope_ids: Vec<u64>,
+    // For each running function, the first scope it opened and the last
+    // scope of the chain it was defined in. Functions run on top of their
+    // caller's scopes; the ones in between belong to the caller and are
+    // not visible to the function.
+    visible: Vec<(usize, usize)>,
+    // A cache of where each name is bound: for each symbol, the (scope,
+    // slot) pairs it is bound to, innermost last, kept in step with pushes
+    // and pops. Names are still looked up at run time, by reading the last
+    // visible entry instead of searching every scope.
+    bindings: Vec<Vec<(usize, usize)>>,
     return_switch: bool,
     return_value: Object,
     injects: Vec<(Token, Object)>,
     in_function: Vec<FunctionType>,
-    class_instance: Vec<Token>,
+    class_instance: Vec<(Object, Object)>,
+    // Tasks spawned since the scheduler last looked, and its logical clock.
+    spawned: Vec<Rc<RefCell<Task>>>,
+    task_count: usize,
+    clock: f64,
+    tail_call: Option<(Object, Vec<Object>)>,
+    // Functions currently running, innermost last. Each is the function's
+    // name with the line it was called from.
+    frames: Vec<Token>,
+    // Where the call about to be made is written, see `set_call_site`.
+    call_site: Option<Token>,
+    max_depth: usize,
 }
 
 impl Environment {
     pub fn global(&mut self) {
-        self.stack[0].define(
-            Token {
-                lexeme: "time".to_owned(),
-                tokentype: TokenType::Identifier,
-                literal: "time".to_owned(),
-                line: 0,
-            },
-            Object::InternalFunction {
-                internaltype: InternalFunction::Time,
-            },
-        );
-        self.stack[0].define(
-            Token {
-                lexeme: "clock".to_owned(),
-                tokentype: TokenType::Identifier,
-                literal: "clock".to_owned(),
-                line: 0,
-            },
-            Object::InternalFunction {
-                internaltype: InternalFunction::Clock,
-            },
-        );
+        let builtins = [
+            ("time", InternalFunction::Time),
+            ("clock", InternalFunction::Clock),
+            ("len", InternalFunction::Len),
+            ("type", InternalFunction::Type),
+            ("fields", InternalFunction::Fields),
+            ("methods", InternalFunction::Methods),
+            ("class_of", InternalFunction::ClassOf),
+            ("has_method", InternalFunction::HasMethod),
+            ("spawn", InternalFunction::Spawn),
+            ("sleep", InternalFunction::Sleep),
+            ("exec", InternalFunction::Exec),
+            ("now", InternalFunction::Now),
+            ("gc_stats", InternalFunction::GcStats),
+        ];
+        for (name, internaltype) in builtins {
+            self.define(Token::internal(name), Object::InternalFunction { internaltype });
+        }
     }
     pub fn new() -> Self {
         let mut memorystack = Vec::<Memory>::new();
         memorystack.push(Memory::new());
         return Environment {
             stack: memorystack,
+            scope_ids: vec![next_scope_id()],
+            visible: Vec::<(usize, usize)>::new(),
+            bindings: Vec::<Vec<(usize, usize)>>::new(),
             return_switch: false,
             return_value: Object::NilObject,
             injects: Vec::<(Token, Object)>::new(),
             in_function: Vec::<FunctionType>::new(),
-            class_instance: Vec::<Token>::new(),
+            class_instance: Vec::<(Object, Object)>::new(),
+            spawned: Vec::<Rc<RefCell<Task>>>::new(),
+            task_count: 0,
+            clock: 0.0,
+            tail_call: None,
+            frames: Vec::<Token>::new(),
+            call_site: None,
+            max_depth: DEFAULT_MAX_DEPTH,
         };
     }
 
-    pub fn new_from_parent(parent: &mut Environment) -> Self {
-        let mut memorystack = Vec::<Memory>::new();
-        memorystack.append(&mut parent.stack);
-        memorystack.push(Memory::new());
-        return Environment {
-            stack: memorystack,
-            return_switch: false,
-            return_value: Object::NilObject,
-            injects: Vec::<(Token, Object)>::new(),
-            in_function: Vec::<FunctionType>::new(),
-            class_instance: Vec::<Token>::new(),
-        };
+    // The innermost binding of `name` the running function can see.
+    fn lookup(&self, name: Symbol) -> Option<(usize, usize)> {
+        let bindings = self.bindings.get(name.index())?;
+        return bindings
+            .iter()
+            .rev()
+            .find(|(scope, _)| self.is_visible(*scope))
+            .copied();
     }
 
-    pub fn stackpush(&mut self, memory: Memory) {
+    fn bind(&mut self, name: Symbol, scope: usize, slot: usize) {
+        if self.bindings.len() <= name.index() {
+            self.bindings.resize(name.index() + 1, Vec::new());
+        }
+        self.bindings[name.index()].push((scope, slot));
+    }
+
+    fn push_scope(&mut self, memory: Memory) {
+        let scope = self.stack.len();
+        for (slot, name) in memory.names.iter().enumerate() {
+            self.bind(*name, scope, slot);
+        }
         self.stack.push(memory);
+        self.scope_ids.push(next_scope_id());
+    }
+
+    fn pop_scope(&mut self) -> Option<Memory> {
+        let memory = self.stack.pop()?;
+        self.scope_ids.pop();
+        for name in &memory.names {
+            self.bindings[name.index()].pop();
+        }
+        return Some(memory);
+    }
+
+    pub fn stackpush(&mut self, memory: Memory) {
+        self.push_scope(memory);
         loop {
             match self.injects.pop() {
                 Some(i) => self.define(i.0, i.1),
@@ -116,106 +267,223 @@ impl Environment {
     }
 
     pub fn stack_temp_push(&mut self) {
-        self.stack.push(Memory::new());
+        self.push_scope(Memory::new());
     }
 
     pub fn stack_temp_pop(&mut self) {
-        self.stack.pop();
+        self.pop_scope();
     }
 
     pub fn inject(&mut self, t: Token, v: Object) {
         self.injects.push((t, v));
     }
 
-    pub fn stackpop(&mut self) -> Option<Memory> {
-        self.injects = Vec::<(Token, Object)>::new();
-        return self.stack.pop();
+    pub fn spawn(&mut self, generator: Rc<RefCell<Generator>>) -> Object {
+        self.task_count += 1;
+        let task = gc::alloc_task(
+            self,
+            Task {
+                id: self.task_count,
+                generator,
+                done: false,
+            },
+        );
+        self.spawned.push(task.clone());
+        return Object::Task(task);
     }
 
-    pub fn define(&mut self, k: Token, v: Object) {
-        let memorysize = self.stack.len() - 1;
-        self.stack[memorysize].define(k, v);
+    pub fn take_spawned(&mut self) -> Vec<Rc<RefCell<Task>>> {
+        return std::mem::take(&mut self.spawned);
     }
 
-    pub fn get(&self, token: Token) -> Object {
-        let mut memorysize = self.stack.len() - 1;
-        loop {
-            match self.stack[memorysize].get(token.clone()) {
-                Some(x) => return x,
-                _ => {
-                    if memorysize > 0 {
-                        memorysize -= 1
-                    } else if memorysize == 0 {
-                        break;
-                    }
-                }
-            }
+    pub fn set_clock(&mut self, clock: f64) {
+        self.clock = clock;
+    }
+
+    pub fn clock(&self) -> f64 {
+        return self.clock;
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // Records the token of a call expression right before the function it
+    // calls starts, so its frame reports that line. Calls the interpreter
+    // makes itself, such as `__str__`, have no site and report the line the
+    // function is defined on.
+    pub fn set_call_site(&mut self, site: Token) {
+        self.call_site = Some(site);
+    }
+
+    // Forgets the site of a call that does not start a frame.
+    pub fn clear_call_site(&mut self) {
+        self.call_site = None;
+    }
+
+    fn frame(&mut self, name: &Token) -> Token {
+        let line = match self.call_site.take() {
+            Some(site) => site.line,
+            None => name.line,
+        };
+        return Token { line, ..*name };
+    }
+
+    pub fn enter_call(&mut self, name: &Token) {
+        if self.frames.len() >= self.max_depth {
+            let frames = self.frames.iter().rev().take(REPORTED_FRAMES).cloned().collect();
+            std::panic::panic_any(RuntimeError::StackOverflow {
+                limit: self.max_depth,
+                frames,
+            });
         }
+        let frame = self.frame(name);
+        self.frames.push(frame);
+    }
 
-        panic!("Undefined variable {}, {:#?}", token.lexeme, self.stack[0]);
+    // A tail call takes over the frame of the function that made it.
+    pub fn replace_call(&mut self, name: &Token) {
+        let frame = self.frame(name);
+        self.frames.pop();
+        self.frames.push(frame);
     }
 
-    pub fn get_from_parent(&self, token: Token) -> Object {
-        let mut memorysize = self.stack.len() - 2 - self.in_function.len();
-        loop {
-            match self.stack[memorysize].get(token.clone()) {
-                Some(x) => return x,
-                _ => {
-                    if memorysize > 0 {
-                        memorysize -= 1
-                    } else if memorysize == 0 {
-                        break;
-                    }
-                }
+    pub fn exit_call(&mut self) {
+        self.frames.pop();
+    }
+
+    // Drops everything but the globals after a runtime error unwound out of
+    // the middle of a call.
+    pub fn unwind(&mut self) {
+        while self.stack.len() > 1 {
+            self.pop_scope();
+        }
+        self.return_switch = false;
+        self.return_value = Object::NilObject;
+        self.injects.clear();
+        self.in_function.clear();
+        self.class_instance.clear();
+        self.tail_call = None;
+        self.frames.clear();
+        self.call_site = None;
+        self.visible.clear();
+    }
+
+    // Visits every value the environment holds on to; these are the roots
+    // the garbage collector marks from.
+    pub fn trace(&self, visit: &mut dyn FnMut(&Object)) {
+        for memory in &self.stack {
+            for value in memory.values() {
+                visit(value);
             }
         }
+        visit(&self.return_value);
+        for (_, value) in &self.injects {
+            visit(value);
+        }
+        for (receiver, class) in &self.class_instance {
+            visit(receiver);
+            visit(class);
+        }
+        if let Some((function, arguments)) = &self.tail_call {
+            visit(function);
+            for argument in arguments {
+                visit(argument);
+            }
+        }
+        for task in &self.spawned {
+            visit(&Object::Task(task.clone()));
+        }
+    }
 
-        panic!("Undefined variable {}, {:#?}", token.lexeme, self.stack[0]);
+    pub fn depth(&self) -> usize {
+        return self.stack.len();
     }
 
-    pub fn need_to_capture(&self, token: Token) -> bool {
-        let oringal_size = self.stack.len() - 1;
-        let mut memorysize = self.stack.len() - 1;
+    pub fn stackpop(&mut self) -> Option<Memory> {
+        self.injects = Vec::<(Token, Object)>::new();
+        return self.pop_scope();
+    }
 
-        loop {
-            match self.stack[memorysize].get(token.clone()) {
-                Some(_) => {
-                    if memorysize == oringal_size {
-                        return false;
-                    } else {
-                        return true;
-                    }
-                }
-                _ => {
-                    if memorysize > 0 {
-                        memorysize -= 1
-                    } else if memorysize == 0 {
-                        break;
-                    }
-                }
+    // Defines `k` in the innermost scope and returns the slot it landed in.
+    fn define_slot(&mut self, k: Token, v: Object) -> usize {
+        let scope = self.stack.len() - 1;
+        match self.lookup(k.lexeme) {
+            Some((s, slot)) if s == scope => {
+                self.stack[scope].set(slot, k, v);
+                return slot;
+            }
+            _ => {
+                let slot = self.stack[scope].push(k, v);
+                self.bind(k.lexeme, scope, slot);
+                return slot;
             }
         }
+    }
 
-        panic!("Undefined variable {}, {:?}", token.lexeme, self.stack);
+    pub fn define(&mut self, k: Token, v: Object) {
+        self.define_slot(k, v);
     }
 
-    pub fn assign(&mut self, token: Token, value: Object) {
-        let mut memorysize = self.stack.len() - 1;
+    pub fn define_constant(&mut self, k: Token, v: Object) {
+        let scope = self.stack.len() - 1;
+        let slot = self.define_slot(k, v);
+        self.stack[scope].constants[slot] = true;
+    }
 
-        loop {
-            match self.stack[memorysize].assign(token.clone(), value.clone()) {
-                Ok(_) => return,
-                _ => {
-                    if memorysize > 0 {
-                        memorysize -= 1
-                    } else if memorysize == 0 {
-                        break;
-                    }
+    // Whether the running function can see the scope at `index`.
+    fn is_visible(&self, index: usize) -> bool {
+        match self.visible.last() {
+            Some((base, home)) => return index >= *base || index <= *home,
+            None => return true,
+        }
+    }
+
+    pub fn get(&self, token: Token) -> Object {
+        match self.find(token) {
+            Some(x) => return x,
+            None => panic!("Undefined variable {}, {:#?}", token.lexeme, self.stack[0]),
+        }
+    }
+
+    // Every visible name, innermost scope first and without duplicates.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::<String>::new();
+        for (index, memory) in self.stack.iter().enumerate().rev() {
+            if !self.is_visible(index) {
+                continue;
+            }
+            for name in memory.names() {
+                if !names.contains(&name) {
+                    names.push(name);
                 }
             }
         }
+        return names;
+    }
 
-        panic!("Undefined variable {}", token.lexeme);
+    pub fn find(&self, token: Token) -> Option<Object> {
+        match self.lookup(token.lexeme) {
+            Some((scope, slot)) => return Some(self.stack[scope].slots[slot].clone()),
+            None => None,
+        }
+    }
+
+    // Globals are looked up when the function runs instead of being captured,
+    // so functions always see their current value. Names that are not
+    // defined yet can only be globals declared later.
+    pub fn need_to_capture(&self, token: Token) -> bool {
+        match self.lookup(token.lexeme) {
+            Some((scope, _)) => return scope != self.stack.len() - 1 && scope != 0,
+            None => return false,
+        }
+    }
+
+    pub fn assign(&mut self, token: Token, value: Object) {
+        match self.lookup(token.lexeme) {
+            Some((scope, slot)) => self.stack[scope].assign_slot(slot, token, value),
+            None => panic!("Undefined variable {}", token.lexeme),
+        }
     }
 
     pub fn set_return(&mut self, value: Object) {
@@ -223,6 +491,15 @@ impl Environment {
         self.return_value = value;
     }
 
+    // Set by `return f(...)`; the enclosing `call_function` makes the call.
+    pub fn set_tail_call(&mut self, function: Object, arguments: Vec<Object>) {
+        self.tail_call = Some((function, arguments));
+    }
+
+    pub fn take_tail_call(&mut self) -> Option<(Object, Vec<Object>)> {
+        return self.tail_call.take();
+    }
+
     pub fn is_set_return(&self) -> bool {
         return self.return_switch;
     }
@@ -238,37 +515,27 @@ impl Environment {
         self.in_function.push(in_function);
     }
 
-    pub fn set_class_instance(&mut self, class_instance: Token) {
-        self.class_instance.push(class_instance);
+    // Records the receiver of the method about to run and the class that
+    // defines the method, which is where `super` lookups start from.
+    pub fn set_class_instance(&mut self, receiver: Object, class: Object) {
+        self.class_instance.push((receiver, class));
     }
 
     pub fn is_in_function(&self) -> bool {
-        match self.in_function.last() {
-            Some(FunctionType::Function) => true,
-            _ => false,
-        }
+        return !self.in_function.is_empty();
     }
     pub fn is_in_method(&self) -> bool {
         match self.in_function.last() {
-            Some(FunctionType::Method) => true,
-            _ => false,
+            Some(FunctionType::Function) | None => false,
+            _ => true,
         }
     }
-    pub fn is_in_constructor(&self) -> bool {
-        match self.in_function.last() {
-            Some(FunctionType::Constructor) => true,
-            _ => false,
-        }
+    pub fn receiver(&self) -> Option<Object> {
+        return self.class_instance.last().map(|(receiver, _)| receiver.clone());
     }
-    pub fn is_class_instance(&mut self) -> Option<Token> {
-        let value = self.class_instance.pop();
-        match value.clone() {
-            Some(v) => {
-                self.class_instance.push(v.clone());
-                value
-            }
-            None => None,
-        }
+
+    pub fn method_class(&self) -> Option<Object> {
+        return self.class_instance.last().map(|(_, class)| class.clone());
     }
 
     pub fn clear_class_instance(&mut self) {
@@ -279,7 +546,41 @@ impl Environment {
         self.in_function.pop();
     }
 
-    pub fn assign_instance(&mut self, k: Token, v: Object) {
-        self.stack[1].define(k, v);
+    // The innermost scope, where a function defined now lives.
+    pub fn current_scope(&self) -> ScopeRef {
+        let index = self.stack.len() - 1;
+        return ScopeRef {
+            index,
+            id: self.scope_ids[index],
+        };
+    }
+
+    // Called before a function's body opens its scopes. Besides its own
+    // scopes, the function sees the chain of scopes it was defined in when
+    // that is still on the stack below it, which is the case for calls made
+    // from within that chain. Otherwise it only sees the globals.
+    pub fn enter_function(&mut self, home: ScopeRef) {
+        let home = match self.scope_ids.get(home.index) {
+            Some(id) if *id == home.id => home.index,
+            _ => 0,
+        };
+        self.visible.push((self.stack.len(), home));
+    }
+
+    pub fn exit_function(&mut self) {
+        self.visible.pop();
+    }
+
+    // Whether a function defined in `home` can still see it once the
+    // running function returns, i.e. it was not defined in one of its scopes.
+    pub fn outlives_return(&self, home: ScopeRef) -> bool {
+        let base = match self.visible.last() {
+            Some((base, _)) => *base,
+            None => return true,
+        };
+        match self.scope_ids.get(home.index) {
+            Some(id) if *id == home.id => return home.index < base,
+            _ => return true,
+        }
     }
 }
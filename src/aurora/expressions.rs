@@ -1,9 +1,15 @@
 use crate::aurora::token::{Token, TokenType};
-use std::mem;
-use chrono::prelude::*;
+use std::{cell::RefCell, fmt, mem, rc::Rc};
 
 use super::{
-    environment::{Environment, Memory},
+    builtins::{call_internal, is_instance_of},
+    environment::{Environment, Memory, ScopeRef},
+    gc,
+    generators::{self, Generator},
+    inline_cache::InlineCache,
+    interner::Symbol,
+    patterns::{MatchArm, Pattern},
+    scheduler::{Task, Wait},
     statements::Statement,
 };
 
@@ -12,12 +18,52 @@ pub enum FunctionType {
     Function,
     Method,
     Constructor,
+    Getter,
+    Setter,
+    Generator,
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum InternalFunction {
     Time,
-    Clock
+    Clock,
+    Len,
+    Type,
+    Fields,
+    Methods,
+    ClassOf,
+    HasMethod,
+    Spawn,
+    Sleep,
+    Exec,
+    Now,
+    GcStats,
+}
+
+// A value of an enum variant such as `Shape.Rect(2, 3)`.
+#[derive(Clone, PartialEq, PartialOrd)]
+pub struct EnumValue {
+    pub enum_name: Symbol,
+    pub tag: Symbol,
+    pub fields: Vec<Symbol>,
+    pub values: Vec<Object>,
+}
+
+impl fmt::Debug for EnumValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.enum_name, self.tag)?;
+        if self.values.is_empty() {
+            return Ok(());
+        }
+        write!(f, "(")?;
+        for (i, (field, value)) in self.fields.iter().zip(self.values.iter()).enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {:?}", field, value)?;
+        }
+        write!(f, ")")
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -25,26 +71,58 @@ pub enum Object {
     StringObject(String),
     NumberObject(f64),
     BoolObject(bool),
+    ListObject(Vec<Object>),
     InternalFunction{
         internaltype: InternalFunction,
     },
+    // Functions and classes keep their code and members behind `Rc`, so
+    // copying one out of a variable or an instance does not copy them.
     FunctionObject {
         name: Token,
-        parameters: Vec<Token>,
-        body: Box<Statement>,
-        captures: Vec<(Token, Object)>,
+        parameters: Rc<Vec<Token>>,
+        body: Rc<Statement>,
+        captures: Rc<Vec<(Token, Object)>>,
         functype: FunctionType,
+        // Where the function was defined, which decides the names it sees.
+        home: ScopeRef,
     },
     Class {
         name: Token,
-        class_env: Box<Environment>,
+        class_env: Rc<Environment>,
+        statics: Rc<RefCell<Memory>>,
+        superclass: Option<Rc<Object>>,
     },
-    ClassInstance {
+    Trait {
+        name: Token,
+        required: Rc<Vec<Token>>,
+        methods: Rc<Vec<Statement>>,
+    },
+    Enum {
         name: Token,
+        variants: Rc<Vec<(Token, Vec<Token>)>>,
+    },
+    // Variants with fields are called like functions to build a value.
+    EnumConstructor(EnumValue),
+    EnumValue(EnumValue),
+    Generator(Rc<RefCell<Generator>>),
+    GeneratorMethod {
+        generator: Rc<RefCell<Generator>>,
+        name: Token,
+    },
+    Task(Rc<RefCell<Task>>),
+    Wait(Wait),
+    // Instances are shared: every copy of the object sees the same fields.
+    ClassInstance {
+        class: Rc<Object>,
+        memory: Rc<RefCell<Memory>>,
+    },
+    // A method looked up on an instance, remembering its receiver and the
+    // class that defines it.
+    BoundMethod {
+        receiver: Box<Object>,
+        method: Box<Object>,
         class: Box<Object>,
-        memory: Memory,
     },
-    ThisObject,
     NilObject,
 }
 
@@ -54,6 +132,11 @@ pub enum Expression {
         name: Token,
         value: Box<Expression>,
     },
+    AssignPattern {
+        pattern: Pattern,
+        equals: Token,
+        value: Box<Expression>,
+    },
     Binary {
         left: Box<Expression>,
         operator: Token,
@@ -67,10 +150,24 @@ pub enum Expression {
     Get {
         object: Box<Expression>,
         name: Token,
+        cache: InlineCache,
     },
     Grouping {
         expression: Box<Expression>,
     },
+    Index {
+        object: Box<Expression>,
+        bracket: Token,
+        index: Box<Expression>,
+    },
+    List {
+        elements: Vec<Expression>,
+    },
+    Match {
+        keyword: Token,
+        subject: Box<Expression>,
+        arms: Vec<MatchArm>,
+    },
     Literal {
         value: Object,
     },
@@ -83,6 +180,7 @@ pub enum Expression {
         object: Box<Expression>,
         name: Token,
         value: Box<Expression>,
+        cache: InlineCache,
     },
     Super {
         keyword: Token,
@@ -110,6 +208,12 @@ impl Expression {
             } => {
                 let left_value = l.evaluate(env);
                 let right_value = r.evaluate(env);
+                if op.tokentype == TokenType::Is {
+                    return Object::BoolObject(is_instance_of(&left_value, &right_value, op));
+                }
+                if let Some(result) = overloaded_binary(env, op, &left_value, &right_value) {
+                    return result;
+                }
                 if mem::discriminant(&left_value) != mem::discriminant(&right_value) {
                     panic!(
                         "Left object {:?} not the same type as right object {:?}",
@@ -225,6 +329,26 @@ impl Expression {
                             );
                         }
                     }
+                    TokenType::Ampersand => {
+                        let (x, y) = integer_operands(op, &left_value, &right_value);
+                        return Object::NumberObject((x & y) as f64);
+                    }
+                    TokenType::Pipe => {
+                        let (x, y) = integer_operands(op, &left_value, &right_value);
+                        return Object::NumberObject((x | y) as f64);
+                    }
+                    TokenType::Caret => {
+                        let (x, y) = integer_operands(op, &left_value, &right_value);
+                        return Object::NumberObject((x ^ y) as f64);
+                    }
+                    TokenType::LessLess => {
+                        let (x, y) = integer_operands(op, &left_value, &right_value);
+                        return Object::NumberObject((x << shift_amount(op, y)) as f64);
+                    }
+                    TokenType::GreaterGreater => {
+                        let (x, y) = integer_operands(op, &left_value, &right_value);
+                        return Object::NumberObject((x >> shift_amount(op, y)) as f64);
+                    }
                     _ => {
                         panic!("Invalid Operator Type {}", op)
                     }
@@ -235,6 +359,11 @@ impl Expression {
                 right: r,
             } => {
                 let right_value = r.evaluate(env);
+                if op.tokentype == TokenType::Minus {
+                    if let Some(result) = call_method(env, &right_value, "__neg__", vec![]) {
+                        return result;
+                    }
+                }
 
                 match op.tokentype {
                     TokenType::Bang => {
@@ -251,36 +380,105 @@ impl Expression {
                             panic!("Operator {} is not valid for values {:?}", op, right_value);
                         }
                     }
+                    TokenType::Tilde => {
+                        return Object::NumberObject(!as_integer(op, &right_value) as f64);
+                    }
                     _ => {
                         panic!("Invalid Operator Type {}", op)
                     }
                 }
             }
             Expression::Variable { name: n } => {
-                return env.get(n.clone());
+                return env.get(*n);
             }
             Expression::Literal { value: v } => {
                 return v.clone();
             }
+            Expression::List { elements } => {
+                return Object::ListObject(elements.iter().map(|x| x.evaluate(env)).collect());
+            }
+            Expression::Match {
+                keyword,
+                subject,
+                arms,
+            } => {
+                let value = subject.evaluate(env);
+                for arm in arms {
+                    let mut bindings = Vec::<(Token, Object)>::new();
+                    if !arm.pattern.matches(&value, &mut bindings) {
+                        continue;
+                    }
+                    env.stackpush(Memory::new());
+                    for (name, v) in bindings {
+                        env.define(name, v);
+                    }
+                    let accepted = match &arm.guard {
+                        Some(guard) => truthy(guard.evaluate(env)),
+                        None => true,
+                    };
+                    if !accepted {
+                        env.stackpop();
+                        continue;
+                    }
+                    let result = arm.body.evaluate(env);
+                    env.stackpop();
+                    return result;
+                }
+                panic!("No match arm for {:?} at line {}", value, keyword.line);
+            }
             Expression::Grouping { expression: e } => {
                 return e.evaluate(env);
             }
+            Expression::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object_value = object.evaluate(env);
+                let index_value = index.evaluate(env);
+                if let (Object::ListObject(l), Object::NumberObject(i)) =
+                    (&object_value, &index_value)
+                {
+                    return match l.get(as_integer(bracket, &index_value) as usize) {
+                        Some(value) if *i >= 0.0 => value.clone(),
+                        _ => panic!("List index {} out of range at {}", i, bracket.line),
+                    };
+                }
+                if let (Object::StringObject(s), Object::NumberObject(i)) =
+                    (&object_value, &index_value)
+                {
+                    return match s.chars().nth(as_integer(bracket, &index_value) as usize) {
+                        Some(c) if *i >= 0.0 => Object::StringObject(c.to_string()),
+                        _ => panic!("String index {} out of range at {}", i, bracket.line),
+                    };
+                }
+                match call_method(env, &object_value, "__index__", vec![index_value.clone()]) {
+                    Some(value) => return value,
+                    None => panic!(
+                        "Cannot index {:?} with {:?} at {}",
+                        object_value, index_value, bracket.line
+                    ),
+                }
+            }
             Expression::Assign { name: n, value: v } => {
                 let value = v.evaluate(env);
-                let injected_v = match value {
-                    Object::ClassInstance {
-                        name: _,
-                        class,
-                        memory,
-                    } => Object::ClassInstance {
-                        name: n.clone(),
-                        class,
-                        memory,
-                    },
-                    _ => value,
-                };
-                env.assign(n.clone(), injected_v.clone());
-                return injected_v;
+                env.assign(*n, value.clone());
+                return value;
+            }
+            Expression::AssignPattern {
+                pattern,
+                equals,
+                value: v,
+            } => {
+                let value = v.evaluate(env);
+                let mut bindings = Vec::<(Token, Object)>::new();
+                if !pattern.matches(&value, &mut bindings) {
+                    panic!("Cannot destructure {:?} at line {}", value, equals.line);
+                }
+                for (name, v) in bindings {
+                    env.assign(name, v);
+                }
+                return value;
             }
             Expression::Logical {
                 left,
@@ -319,160 +517,54 @@ impl Expression {
                 paren,
                 arguments,
             } => {
-                let callee = callee.evaluate(env);
-                match callee.clone() {
-                    Object::FunctionObject {
-                        name,
-                        parameters,
-                        body,
-                        captures,
-                        functype,
-                    } => {
-                        if arguments.len() != parameters.len() {
-                            panic!("Wrong Number of arguments for function {}", &name);
-                        }
-                        for capture in captures {
-                            env.inject(capture.0, capture.1);
-                        }
+                // `a.m(...)` calls the method without binding it first.
+                if let Expression::Get {
+                    object,
+                    name,
+                    cache,
+                } = &**callee
+                {
+                    let instance = object.evaluate(env);
+                    if let Some((method, defining)) = method_for_call(&instance, name, cache) {
                         let arguments_values: Vec<Object> =
-                            arguments.into_iter().map(|x| x.evaluate(env)).collect();
-                        for i in 0..parameters.len() {
-                            env.inject(parameters[i].clone(), arguments_values[i].clone());
-                        }
-                        env.set_in_function(functype);
-                        body.evaluate(env);
-                        env.clear_class_instance();
-                        env.clear_in_function();
-                        return env.unset_return();
-                    }
-                    Object::Class {
-                        name: n,
-                        mut class_env,
-                    } => {
-                        let constructor = class_env.get(n.clone());
-                        let mut instance_memory = Memory::new();
-                        match constructor {
-                            Object::FunctionObject {
-                                name: constructor_name,
-                                parameters,
-                                body,
-                                captures,
-                                functype: _,
-                            } => {
-                                class_env.define(
-                                    n.clone(),
-                                    Object::FunctionObject {
-                                        name: constructor_name,
-                                        parameters,
-                                        body,
-                                        captures,
-                                        functype: FunctionType::Constructor,
-                                    },
-                                );
-                                class_env.set_in_function(FunctionType::Constructor);
-                                class_env.stackpush(instance_memory);
-                                let init = Expression::Call {
-                                    callee: Box::new(Expression::Variable { name: n.clone() }),
-                                    paren: paren.clone(),
-                                    arguments: arguments.clone(),
-                                };
-                                init.evaluate(&mut class_env);
-                                class_env.clear_in_function();
-                                instance_memory = match class_env.stackpop() {
-                                    Some(m) => m,
-                                    _ => Memory::new(),
-                                }
-                            }
-                            Object::NilObject => (),
-                            _ => panic!("Invalid constructor for {:#?}", n),
-                        }
-                        let instance = Object::ClassInstance {
-                            name: n.clone(),
-                            class: Box::new(callee.clone()),
-                            memory: instance_memory,
-                        };
-                        return instance;
-                    }
-                    Object::InternalFunction { internaltype } => {
-                        match internaltype {
-                            InternalFunction::Time => {
-                                return Object::StringObject(Local::now().to_string())
-                            }
-                            InternalFunction::Clock => {
-                                return Object::NumberObject(Local::now().timestamp() as f64)
-                            }
-                        }
+                            arguments.iter().map(|x| x.evaluate(env)).collect();
+                        env.set_call_site(*paren);
+                        return call_bound(env, instance, defining, method, arguments_values);
                     }
-                    _ => panic!("Object {} not a function at {}", paren.lexeme, paren.line),
+                    let callee = get_property(env, instance, object, name, cache);
+                    return call(env, callee, arguments, paren);
                 }
+                let callee = callee.evaluate(env);
+                return call(env, callee, arguments, paren);
             }
-            Expression::Get { object, name } => {
-                let envname = match &*(*object) {
-                    Expression::Variable { name } => name.clone(),
-                    Expression::This { keyword } => keyword.clone(),
-                    _ => panic!("Must set property on object {:?}", object),
-                };
+            Expression::Get {
+                object,
+                name,
+                cache,
+            } => {
                 let instance = object.evaluate(env);
-                match instance {
-                    Object::ClassInstance {
-                        name: _,
-                        class,
-                        memory,
-                    } => {
-                        match *class {
-                            Object::Class {
-                                name: _,
-                                mut class_env,
-                            } => {
-                                class_env.stackpush(memory);
-                                let value = class_env.get(name.clone());
-                                class_env.stackpop();
-                                env.set_class_instance(envname);
-                                return value;
-                            }
-                            _ => panic!("instance parent is not a class {:?}", &class),
-                        };
-                    }
-                    _ => panic!(
-                        "cannot call property {} on non-instance object {:?}",
-                        name.clone(),
-                        object
-                    ),
-                }
+                return get_property(env, instance, object, name, cache);
             }
             Expression::Set {
                 object,
                 name,
                 value,
+                cache,
             } => {
                 let instance = object.evaluate(env);
                 let set_value = value.evaluate(env);
                 match instance.clone() {
-                    Object::ClassInstance {
-                        name: n,
-                        class: c,
-                        mut memory,
-                    } => {
-                        (&mut memory).define(name.clone(), set_value);
-                        let instance = Object::ClassInstance {
-                            name: n,
-                            class: c,
-                            memory,
-                        };
-                        let envname = match &*(*object) {
-                            Expression::Variable { name } => name.clone(),
-                            Expression::This { keyword:_ } => match env.is_class_instance() {
-                                Some(x) => x,
-                                None => panic!("Invalid class instance {:#?}", instance),
-                            },
-                            _ => panic!("Must set property on object {:?}", object),
-                        };
-                        env.assign(envname, instance.clone());
-                        if !env.is_in_method() {
-                            env.clear_class_instance();
+                    Object::ClassInstance { class, memory } => {
+                        if let Some((setter, defining)) = cache.lookup(&class, name, Token::setter) {
+                            env.set_call_site(*name);
+                            call_bound(env, instance, defining, setter, vec![set_value]);
+                            return Object::NilObject;
                         }
+                        cache.define_field(&mut memory.borrow_mut(), *name, set_value);
+                    }
+                    Object::Class { statics, .. } => {
+                        statics.borrow_mut().define(*name, set_value);
                     }
-                    Object::ThisObject => env.assign_instance(name.clone(), set_value),
                     _ => panic!(
                         "cannot call property {} on non-instance object {:?}",
                         name.clone(),
@@ -482,11 +574,8 @@ impl Expression {
                 return Object::NilObject;
             }
             Expression::This { keyword } => {
-                if env.is_in_constructor() {
-                    return Object::ThisObject;
-                }
-                match (env.is_class_instance(), env.is_in_method()) {
-                    (Some(x), true) => return env.get(x),
+                match (env.receiver(), env.is_in_method()) {
+                    (Some(receiver), true) => return receiver,
                     _ => panic!(
                         "Invalid use of this outside of class or outside of method at {}",
                         keyword
@@ -494,33 +583,36 @@ impl Expression {
                 }
             }
             Expression::Super { keyword, method } => {
-                if env.is_in_constructor() {
-                    return env.get(method.clone());
-                }
-                match (env.is_class_instance(), env.is_in_method()) {
-                    (Some(x), true) => match env.get(x.clone()) {
-                        Object::ClassInstance { name:_, class, memory:_ } => {
-                            match *class {
-                               Object::Class { name:n, class_env } => {
-                                // panic!("TEst {:#?}", env.get(n.clone()));
-                                return class_env.get_from_parent(method.clone())
-                               },
-                               _ => panic!(
-                                "Invalid use of Super outside of class or outside of method at {}",
-                                keyword
-                            ),
-                            }
-                        },
-                        _ => panic!(
-                            "Invalid use of Super outside of class or outside of method at {}",
-                            keyword
-                        ),
-                    },
+                let receiver = match (env.receiver(), env.is_in_method()) {
+                    (Some(receiver), true) => receiver,
                     _ => panic!(
                         "Invalid use of Super outside of class or outside of method at {}",
                         keyword
                     ),
-                }                
+                };
+                // Start from the parent of the class that defined the running
+                // method, not the receiver's class, so overrides further down
+                // the hierarchy are skipped.
+                let superclass = match env.method_class() {
+                    Some(Object::Class {
+                        superclass: Some(superclass),
+                        ..
+                    }) => superclass,
+                    _ => panic!("Invalid use of Super in class without superclass at {}", keyword),
+                };
+                match lookup_method(&superclass, *method) {
+                    Some((value, defining)) => {
+                        return Object::BoundMethod {
+                            receiver: Box::new(receiver),
+                            method: Box::new(value),
+                            class: Box::new(defining),
+                        }
+                    }
+                    None => panic!(
+                        "Undefined method {} on superclass at {}",
+                        method.lexeme, method.line
+                    ),
+                }
             }
         }
     }
@@ -529,11 +621,23 @@ impl Expression {
         match self {
             Expression::Assign { name, value } => {
                 value.resolve(captures, env);
-                match env.need_to_capture(name.clone()) {
-                    true => captures.push((name.clone(), env.get(name.clone()))),
+                match env.need_to_capture(*name) {
+                    true => captures.push((*name, env.get(*name))),
                     false => (),
                 }
             }
+            Expression::AssignPattern {
+                pattern,
+                equals: _,
+                value,
+            } => {
+                value.resolve(captures, env);
+                for name in pattern.bindings() {
+                    if env.need_to_capture(name) {
+                        captures.push((name, env.get(name)));
+                    }
+                }
+            }
             Expression::Binary {
                 left,
                 operator: _,
@@ -552,11 +656,39 @@ impl Expression {
                     v.resolve(captures, env);
                 }
             }
-            Expression::Get { object, name: _ } => {
+            Expression::Get { object, .. } => {
                 object.resolve(captures, env);
             }
             Expression::Grouping { expression } => expression.resolve(captures, env),
+            Expression::Index {
+                object,
+                bracket: _,
+                index,
+            } => {
+                object.resolve(captures, env);
+                index.resolve(captures, env);
+            }
             Expression::Literal { value: _ } => (),
+            Expression::List { elements } => {
+                for element in elements {
+                    element.resolve(captures, env);
+                }
+            }
+            // Pattern bindings live in the arm's own scope at run time, so
+            // capturing an outer variable of the same name is harmless.
+            Expression::Match {
+                keyword: _,
+                subject,
+                arms,
+            } => {
+                subject.resolve(captures, env);
+                for arm in arms {
+                    if let Some(guard) = &arm.guard {
+                        guard.resolve(captures, env);
+                    }
+                    arm.body.resolve(captures, env);
+                }
+            }
             Expression::Logical {
                 left,
                 operator: _,
@@ -569,6 +701,7 @@ impl Expression {
                 object,
                 name: _,
                 value,
+                cache: _,
             } => {
                 object.resolve(captures, env);
                 value.resolve(captures, env);
@@ -576,10 +709,526 @@ impl Expression {
             Expression::Super { keyword:_, method:_ } => (),
             Expression::This { keyword: _ } => (),
             Expression::Unary { operator: _, right } => right.resolve(captures, env),
-            Expression::Variable { name } => match env.need_to_capture(name.clone()) {
-                true => captures.push((name.clone(), env.get(name.clone()))),
+            Expression::Variable { name } => match env.need_to_capture(*name) {
+                true => captures.push((*name, env.get(*name))),
                 false => (),
             },
         }
     }
 }
+
+
+// Reads property `name` of an already evaluated `object`.
+fn get_property(
+    env: &mut Environment,
+    instance: Object,
+    object: &Expression,
+    name: &Token,
+    cache: &InlineCache,
+) -> Object {
+    match instance.clone() {
+        Object::Generator(generator) => {
+            return Object::GeneratorMethod {
+                generator,
+                name: *name,
+            }
+        }
+        Object::Task(task) if name.lexeme == "done" => {
+            return Object::BoolObject(task.borrow().done)
+        }
+        Object::Wait(Wait::Process { result, .. }) => match name.lexeme.as_str() {
+            "status" => match result.borrow().status {
+                Some(code) => return Object::NumberObject(code),
+                None => return Object::NilObject,
+            },
+            "output" => return Object::StringObject(result.borrow().output.clone()),
+            _ => panic!("Undefined property {} at {}", name.lexeme, name.line),
+        },
+        Object::ClassInstance { class, memory } => {
+            if let Some(value) = cache.field(&memory.borrow(), *name) {
+                return value;
+            }
+            match cache.lookup(&class, name, |name| *name) {
+                Some((
+                    value @ Object::FunctionObject {
+                        functype: FunctionType::Getter,
+                        ..
+                    },
+                    defining,
+                )) => {
+                    env.set_call_site(*name);
+                    return call_bound(env, instance, defining, value, vec![]);
+                }
+                Some((
+                    value @ Object::FunctionObject {
+                        functype: FunctionType::Method | FunctionType::Generator,
+                        ..
+                    },
+                    defining,
+                )) => {
+                    return Object::BoundMethod {
+                        receiver: Box::new(instance),
+                        method: Box::new(value),
+                        class: Box::new(defining),
+                    }
+                }
+                Some((value, _)) => return value,
+                None => panic!("Undefined property {} at {}", name.lexeme, name.line),
+            }
+        }
+        Object::Enum {
+            name: enum_name,
+            variants,
+        } => {
+            for (tag, fields) in variants.iter() {
+                if tag.lexeme != name.lexeme {
+                    continue;
+                }
+                let value = EnumValue {
+                    enum_name: enum_name.lexeme,
+                    tag: tag.lexeme,
+                    fields: fields.iter().map(|f| f.lexeme).collect(),
+                    values: Vec::<Object>::new(),
+                };
+                if fields.is_empty() {
+                    return Object::EnumValue(value);
+                }
+                return Object::EnumConstructor(value);
+            }
+            panic!(
+                "Undefined variant {} on enum {}",
+                name.lexeme, enum_name.lexeme
+            );
+        }
+        Object::EnumValue(value) => {
+            if name.lexeme == "tag" {
+                return Object::StringObject(value.tag.to_string());
+            }
+            match value.fields.iter().position(|f| *f == name.lexeme) {
+                Some(i) => return value.values[i].clone(),
+                None => panic!(
+                    "Variant {}.{} has no field {}",
+                    value.enum_name, value.tag, name.lexeme
+                ),
+            }
+        }
+        Object::Class { name: class_name, .. } => {
+            match lookup_static(&instance, *name) {
+                Some(value) => return value,
+                None => panic!(
+                    "Undefined static member {} on class {}",
+                    name.lexeme, class_name.lexeme
+                ),
+            }
+        }
+        _ => panic!(
+            "cannot call property {} on non-instance object {:?}",
+            name.clone(),
+            object
+        ),
+    }
+}
+
+// The method `instance.name(...)` calls, when `name` is a plain or generator
+// method rather than a field, getter or anything else.
+fn method_for_call(instance: &Object, name: &Token, cache: &InlineCache) -> Option<(Object, Object)> {
+    match instance {
+        Object::ClassInstance { class, memory } => {
+            if cache.field(&memory.borrow(), *name).is_some() {
+                return None;
+            }
+            match cache.lookup(class, name, |name| *name)? {
+                member @ (
+                    Object::FunctionObject {
+                        functype: FunctionType::Method | FunctionType::Generator,
+                        ..
+                    },
+                    _,
+                ) => return Some(member),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    }
+}
+
+// Calls an already evaluated callee with unevaluated argument expressions.
+pub fn call(env: &mut Environment, callee: Object, arguments: &Vec<Expression>, paren: &Token) -> Object {
+    match callee.clone() {
+        Object::FunctionObject { .. } => {
+            let arguments_values: Vec<Object> =
+                arguments.into_iter().map(|x| x.evaluate(env)).collect();
+            env.set_call_site(*paren);
+            return call_function(env, callee, arguments_values);
+        }
+        Object::BoundMethod {
+            receiver,
+            method,
+            class,
+        } => {
+            let arguments_values: Vec<Object> =
+                arguments.iter().map(|x| x.evaluate(env)).collect();
+            env.set_call_site(*paren);
+            return call_bound(env, *receiver, *class, *method, arguments_values);
+        }
+        Object::Class {
+            name: n,
+            class_env,
+            statics: _,
+            superclass: _,
+        } => {
+            let instance = Object::ClassInstance {
+                class: Rc::new(callee.clone()),
+                memory: gc::alloc_memory(env, Memory::new()),
+            };
+            match class_env.get(n) {
+                constructor @ Object::FunctionObject { .. } => {
+                    let arguments_values: Vec<Object> =
+                        arguments.iter().map(|x| x.evaluate(env)).collect();
+                    env.set_call_site(*paren);
+                    return construct(env, instance, &callee, constructor, arguments_values);
+                }
+                Object::NilObject => return instance,
+                _ => panic!("Invalid constructor for {:#?}", n),
+            }
+        }
+        Object::GeneratorMethod { generator, name } => {
+            let arguments_values: Vec<Object> =
+                arguments.iter().map(|x| x.evaluate(env)).collect();
+            return generators::call_generator_method(
+                env,
+                &generator,
+                &name,
+                arguments_values,
+            );
+        }
+        Object::EnumConstructor(mut value) => {
+            if arguments.len() != value.fields.len() {
+                panic!(
+                    "Wrong Number of arguments for variant {}.{} at {}",
+                    value.enum_name, value.tag, paren.line
+                );
+            }
+            value.values = arguments.iter().map(|x| x.evaluate(env)).collect();
+            return Object::EnumValue(value);
+        }
+        Object::InternalFunction { internaltype } => {
+            let arguments_values: Vec<Object> =
+                arguments.iter().map(|x| x.evaluate(env)).collect();
+            return call_internal(env, internaltype, arguments_values, paren);
+        }
+        _ => panic!("Object {} not a function at {}", paren.lexeme, paren.line),
+    }
+}
+
+// Runs a function object on top of the caller's environment and returns the
+// value it set with `return`, or nil. A `return f(...)` in the body leaves a
+// pending tail call instead of recursing, which is run here in the same Rust
+// frame once the body has unwound.
+pub fn call_function(env: &mut Environment, function: Object, arguments: Vec<Object>) -> Object {
+    let mut function = function;
+    let mut arguments = arguments;
+    let mut bound = false;
+    let mut entered = false;
+    loop {
+        match function {
+            Object::FunctionObject {
+                name,
+                parameters,
+                body,
+                captures,
+                functype,
+                home,
+            } => {
+                if functype == FunctionType::Generator {
+                    env.clear_call_site();
+                    return generators::create(
+                        env,
+                        Object::FunctionObject {
+                            name,
+                            parameters,
+                            body,
+                            captures,
+                            functype,
+                            home,
+                        },
+                        arguments,
+                        None,
+                    );
+                }
+                if arguments.len() != parameters.len() {
+                    panic!("Wrong Number of arguments for function {}", &name);
+                }
+                match entered {
+                    true => env.replace_call(&name),
+                    false => env.enter_call(&name),
+                }
+                entered = true;
+                for capture in captures.iter() {
+                    env.inject(capture.0, capture.1.clone());
+                }
+                for i in 0..parameters.len() {
+                    env.inject(parameters[i], arguments[i].clone());
+                }
+                env.set_in_function(functype);
+                env.enter_function(home);
+                body.evaluate(env);
+                env.exit_function();
+                env.clear_in_function();
+                if bound {
+                    env.clear_class_instance();
+                }
+                let value = env.unset_return();
+                match env.take_tail_call() {
+                    Some((Object::BoundMethod {
+                        receiver,
+                        method,
+                        class,
+                    }, next_arguments)) => {
+                        env.set_class_instance(*receiver, *class);
+                        function = *method;
+                        arguments = next_arguments;
+                        bound = true;
+                    }
+                    Some((next, next_arguments)) => {
+                        function = next;
+                        arguments = next_arguments;
+                        bound = false;
+                    }
+                    None => {
+                        env.exit_call();
+                        return value;
+                    }
+                }
+            }
+            _ => panic!("Object {:?} is not a function", function),
+        }
+    }
+}
+
+// Whether `return callee(...)` can hand the call back to `call_function`
+// rather than making it from inside the current frame. That call runs once
+// the current function's scopes are gone, so a function defined in them
+// cannot wait for it.
+pub fn is_tail_callable(env: &Environment, callee: &Object) -> bool {
+    match callee {
+        Object::FunctionObject { functype, home, .. } => {
+            *functype == FunctionType::Function && env.outlives_return(*home)
+        }
+        Object::BoundMethod { method, .. } => match &**method {
+            Object::FunctionObject { functype, .. } => *functype == FunctionType::Method,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Finds a member in `class` or the nearest superclass defining it, along
+// with the class it was found in.
+pub fn lookup_method(class: &Object, name: Token) -> Option<(Object, Object)> {
+    let mut current = class;
+    loop {
+        match current {
+            Object::Class {
+                class_env,
+                superclass,
+                ..
+            } => {
+                if let Some(value) = class_env.find(name) {
+                    return Some((value, current.clone()));
+                }
+                match superclass {
+                    Some(s) => current = s,
+                    None => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn lookup_static(class: &Object, name: Token) -> Option<Object> {
+    let mut current = class;
+    loop {
+        match current {
+            Object::Class {
+                statics,
+                superclass,
+                ..
+            } => {
+                if let Some(value) = statics.borrow().get(name) {
+                    return Some(value);
+                }
+                match superclass {
+                    Some(s) => current = s,
+                    None => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+}
+
+pub fn find_method(receiver: &Object, name: &str) -> Option<(Object, Object)> {
+    match receiver {
+        Object::ClassInstance { class, .. } => match lookup_method(class, Token::lookup(name)?) {
+            Some((method @ Object::FunctionObject { .. }, defining)) => {
+                return Some((method, defining))
+            }
+            _ => return None,
+        },
+        _ => return None,
+    }
+}
+
+// Runs a method with `this` bound to `receiver`. `class` is the class that
+// defines the method, where `super` lookups start from.
+pub fn call_bound(
+    env: &mut Environment,
+    receiver: Object,
+    class: Object,
+    method: Object,
+    arguments: Vec<Object>,
+) -> Object {
+    if let Object::FunctionObject {
+        functype: FunctionType::Generator,
+        ..
+    } = method
+    {
+        env.clear_call_site();
+        return generators::create(env, method, arguments, Some((receiver, class)));
+    }
+    env.set_class_instance(receiver, class);
+    let result = call_function(env, method, arguments);
+    env.clear_class_instance();
+    return result;
+}
+
+// Runs a constructor on a fresh instance; fields set through `this` land in
+// the shared instance memory.
+fn construct(
+    env: &mut Environment,
+    instance: Object,
+    class: &Object,
+    constructor: Object,
+    arguments: Vec<Object>,
+) -> Object {
+    call_bound(env, instance.clone(), class.clone(), constructor, arguments);
+    return instance;
+}
+
+// Calls a special method such as `__add__` on `receiver`. Returns None when
+// the receiver does not define it.
+pub fn call_method(
+    env: &mut Environment,
+    receiver: &Object,
+    name: &str,
+    arguments: Vec<Object>,
+) -> Option<Object> {
+    let (method, defining) = find_method(receiver, name)?;
+    return Some(call_bound(env, receiver.clone(), defining, method, arguments));
+}
+
+fn truthy(value: Object) -> bool {
+    return !matches!(value, Object::BoolObject(false) | Object::NilObject);
+}
+
+// Falls back to special methods on class instances for operators the
+// built-in rules don't cover. Ordering operators are handled by `compare`.
+fn overloaded_binary(
+    env: &mut Environment,
+    op: &Token,
+    left: &Object,
+    right: &Object,
+) -> Option<Object> {
+    let args = vec![right.clone()];
+    match op.tokentype {
+        TokenType::Plus => match (left, right) {
+            (Object::StringObject(x), _) => {
+                return match call_method(env, right, "__str__", vec![])? {
+                    Object::StringObject(y) => Some(Object::StringObject(format!("{x}{y}"))),
+                    other => panic!("__str__ must return a string, got {:?}", other),
+                }
+            }
+            (_, Object::StringObject(y)) if find_method(left, "__add__").is_none() => {
+                return match call_method(env, left, "__str__", vec![])? {
+                    Object::StringObject(x) => Some(Object::StringObject(format!("{x}{y}"))),
+                    other => panic!("__str__ must return a string, got {:?}", other),
+                }
+            }
+            _ => return call_method(env, left, "__add__", args),
+        },
+        TokenType::Minus => return call_method(env, left, "__sub__", args),
+        TokenType::Star => return call_method(env, left, "__mul__", args),
+        TokenType::Slash => return call_method(env, left, "__div__", args),
+        TokenType::EqualEqual => return call_method(env, left, "__eq__", args),
+        TokenType::BangEqual => {
+            let equal = call_method(env, left, "__eq__", args)?;
+            return Some(Object::BoolObject(!truthy(equal)));
+        }
+        TokenType::Less => return compare(env, "__lt__", left, right),
+        TokenType::Greater => return compare(env, "__gt__", left, right),
+        TokenType::LessEqual => return compare(env, "__le__", left, right),
+        TokenType::GreaterEqual => return compare(env, "__ge__", left, right),
+        _ => return None,
+    }
+}
+
+// An ordering operator calls the left operand's own method for it when
+// there is one, and is otherwise derived from its `__lt__` and `__eq__`.
+// When only the right operand has them the comparison is reflected onto it,
+// so `5 < m` is handled as `m > 5`.
+fn compare(env: &mut Environment, method: &str, left: &Object, right: &Object) -> Option<Object> {
+    if let Some(result) = call_method(env, left, method, vec![right.clone()]) {
+        return Some(result);
+    }
+    let (receiver, other, method) = match find_method(left, "__lt__") {
+        Some(_) => (left, right, method),
+        None => {
+            let reflected = match method {
+                "__lt__" => "__gt__",
+                "__gt__" => "__lt__",
+                "__le__" => "__ge__",
+                _ => "__le__",
+            };
+            if let Some(result) = call_method(env, right, reflected, vec![left.clone()]) {
+                return Some(result);
+            }
+            find_method(right, "__lt__")?;
+            (right, left, reflected)
+        }
+    };
+    let less = truthy(call_method(env, receiver, "__lt__", vec![other.clone()])?);
+    let result = match method {
+        "__lt__" => less,
+        "__ge__" => !less,
+        "__gt__" => !less && !equal(env, receiver, other),
+        _ => less || equal(env, receiver, other),
+    };
+    return Some(Object::BoolObject(result));
+}
+
+fn equal(env: &mut Environment, receiver: &Object, other: &Object) -> bool {
+    match call_method(env, receiver, "__eq__", vec![other.clone()]) {
+        Some(value) => return truthy(value),
+        None => return receiver == other,
+    }
+}
+
+fn as_integer(op: &Token, value: &Object) -> i64 {
+    match value {
+        Object::NumberObject(x) if x.fract() == 0.0 && x.abs() <= i64::MAX as f64 => *x as i64,
+        _ => panic!("Operator {} requires integer operands, got {:?}", op, value),
+    }
+}
+
+fn integer_operands(op: &Token, left: &Object, right: &Object) -> (i64, i64) {
+    return (as_integer(op, left), as_integer(op, right));
+}
+
+fn shift_amount(op: &Token, amount: i64) -> u32 {
+    if !(0..64).contains(&amount) {
+        panic!("Operator {} shift amount {} out of range 0..64", op, amount);
+    }
+    return amount as u32;
+}
@@ -0,0 +1,396 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    rc::{Rc, Weak},
+};
+
+use super::{
+    environment::{Environment, Memory},
+    expressions::Object,
+    generators::Generator,
+    scheduler::Task,
+};
+
+// Cycle collector for the shared parts of the object graph. Values are still
+// reference counted; the heap only keeps weak handles to every instance,
+// static table, generator and task so it can find groups of them that keep
+// each other alive after the program has dropped them.
+//
+// A collection works like CPython's: an object whose strong count is higher
+// than the number of references other tracked objects hold to it is referenced
+// from outside the heap (the environment stack, call frames, or Rust locals in
+// the middle of evaluating an expression) and is live. So is everything
+// reachable from the environment roots or from a live object. Whatever is left
+// is only kept alive by cycles, and is cleared to break them.
+//
+// Closure captures and class data are shared through plain `Rc`s, which are
+// not tracked. A collection still counts them as objects of their own, so a
+// container reached from several places is only counted once against what
+// it holds.
+enum Tracked {
+    Memory(Weak<RefCell<Memory>>),
+    Generator(Weak<RefCell<Generator>>),
+    Task(Weak<RefCell<Task>>),
+}
+
+impl Tracked {
+    fn address(&self) -> usize {
+        match self {
+            Tracked::Memory(w) => w.as_ptr() as *const u8 as usize,
+            Tracked::Generator(w) => w.as_ptr() as *const u8 as usize,
+            Tracked::Task(w) => w.as_ptr() as *const u8 as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Tracked::Memory(w) => w.strong_count(),
+            Tracked::Generator(w) => w.strong_count(),
+            Tracked::Task(w) => w.strong_count(),
+        }
+    }
+
+    // Addresses of the objects this one refers to, or None when it is
+    // borrowed right now and cannot be looked at.
+    fn children(&self, shared: &mut Shared) -> Option<Vec<usize>> {
+        let mut out = Vec::<usize>::new();
+        match self {
+            Tracked::Memory(w) => {
+                let rc = w.upgrade()?;
+                let memory = rc.try_borrow().ok()?;
+                for value in memory.values() {
+                    edges(value, &mut out, shared);
+                }
+            }
+            Tracked::Generator(w) => {
+                let rc = w.upgrade()?;
+                let generator = rc.try_borrow().ok()?;
+                generator.trace(&mut |value| edges(value, &mut out, shared));
+            }
+            Tracked::Task(w) => {
+                let rc = w.upgrade()?;
+                let task = rc.try_borrow().ok()?;
+                out.push(address(&task.generator));
+            }
+        }
+        return Some(out);
+    }
+
+    // Drops the contents so the cycle through this object falls apart. The
+    // old contents are dropped after the borrow is released.
+    fn clear(&self) {
+        match self {
+            Tracked::Memory(w) => {
+                if let Some(rc) = w.upgrade() {
+                    let old = mem::replace(&mut *rc.borrow_mut(), Memory::new());
+                    drop(old);
+                }
+            }
+            Tracked::Generator(w) => {
+                if let Some(rc) = w.upgrade() {
+                    let old = rc.borrow_mut().take_state();
+                    drop(old);
+                }
+            }
+            // A task only points at its generator, which is cleared instead.
+            Tracked::Task(_) => (),
+        }
+    }
+}
+
+fn address<T>(rc: &Rc<T>) -> usize {
+    return Rc::as_ptr(rc) as *const u8 as usize;
+}
+
+// The untracked `Rc` containers met during one collection, by address,
+// with their strong count and the objects they refer to.
+#[derive(Default)]
+struct Shared {
+    containers: HashMap<usize, (usize, Vec<usize>)>,
+}
+
+impl Shared {
+    // Records `rc` as a child in `out`, walking its contents with `walk` the
+    // first time it is met.
+    fn visit<T>(
+        &mut self,
+        rc: &Rc<T>,
+        out: &mut Vec<usize>,
+        walk: impl FnOnce(&T, &mut Vec<usize>, &mut Shared),
+    ) {
+        let key = address(rc);
+        out.push(key);
+        if self.containers.contains_key(&key) {
+            return;
+        }
+        self.containers.insert(key, (Rc::strong_count(rc), Vec::new()));
+        let mut children = Vec::<usize>::new();
+        walk(rc, &mut children, self);
+        self.containers.insert(key, (Rc::strong_count(rc), children));
+    }
+}
+
+// Collects the objects directly referenced by `value`: tracked objects and
+// shared containers. Values stored inline, such as lists, are looked through.
+fn edges(value: &Object, out: &mut Vec<usize>, shared: &mut Shared) {
+    match value {
+        Object::ListObject(items) => {
+            for item in items {
+                edges(item, out, shared);
+            }
+        }
+        Object::FunctionObject { captures, .. } => {
+            shared.visit(captures, out, |captures, out, shared| {
+                for (_, captured) in captures.iter() {
+                    edges(captured, out, shared);
+                }
+            });
+        }
+        Object::Class {
+            class_env,
+            statics,
+            superclass,
+            ..
+        } => {
+            shared.visit(class_env, out, |class_env, out, shared| {
+                class_env.trace(&mut |v| edges(v, out, shared));
+            });
+            out.push(address(statics));
+            if let Some(s) = superclass {
+                shared.visit(s, out, edges);
+            }
+        }
+        Object::EnumConstructor(v) | Object::EnumValue(v) => {
+            for item in &v.values {
+                edges(item, out, shared);
+            }
+        }
+        Object::Generator(generator) | Object::GeneratorMethod { generator, .. } => {
+            out.push(address(generator))
+        }
+        Object::Task(task) => out.push(address(task)),
+        Object::ClassInstance { class, memory } => {
+            shared.visit(class, out, edges);
+            out.push(address(memory));
+        }
+        Object::BoundMethod {
+            receiver,
+            method,
+            class,
+        } => {
+            edges(receiver, out, shared);
+            edges(method, out, shared);
+            edges(class, out, shared);
+        }
+        _ => (),
+    }
+}
+
+const INITIAL_THRESHOLD: usize = 1000;
+
+struct Heap {
+    objects: Vec<Tracked>,
+    // Allocations since the last collection, and how many trigger the next.
+    pending: usize,
+    threshold: usize,
+    stress: bool,
+    allocations: usize,
+    collections: usize,
+    freed: usize,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const {
+        RefCell::new(Heap {
+            objects: Vec::new(),
+            pending: 0,
+            threshold: INITIAL_THRESHOLD,
+            stress: false,
+            allocations: 0,
+            collections: 0,
+            freed: 0,
+        })
+    };
+}
+
+// Collect on every allocation, to shake out objects the collector misses.
+pub fn set_stress(stress: bool) {
+    HEAP.with(|heap| heap.borrow_mut().stress = stress);
+}
+
+fn track(env: &Environment, tracked: Tracked) {
+    let due = HEAP.with(|heap| {
+        let heap = heap.borrow();
+        heap.stress || heap.pending >= heap.threshold
+    });
+    if due {
+        collect(env);
+    }
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.push(tracked);
+        heap.pending += 1;
+        heap.allocations += 1;
+    });
+}
+
+pub fn alloc_memory(env: &Environment, memory: Memory) -> Rc<RefCell<Memory>> {
+    let rc = Rc::new(RefCell::new(memory));
+    track(env, Tracked::Memory(Rc::downgrade(&rc)));
+    return rc;
+}
+
+pub fn alloc_generator(env: &Environment, generator: Generator) -> Rc<RefCell<Generator>> {
+    let rc = Rc::new(RefCell::new(generator));
+    track(env, Tracked::Generator(Rc::downgrade(&rc)));
+    return rc;
+}
+
+pub fn alloc_task(env: &Environment, task: Task) -> Rc<RefCell<Task>> {
+    let rc = Rc::new(RefCell::new(task));
+    track(env, Tracked::Task(Rc::downgrade(&rc)));
+    return rc;
+}
+
+// Runs a full collection and returns how many objects were cleared.
+pub fn collect(env: &Environment) -> usize {
+    let objects = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.retain(|t| t.strong_count() > 0);
+        mem::take(&mut heap.objects)
+    });
+
+    // Tracked objects come first, then the shared containers found while
+    // walking them and the roots; only tracked objects are ever cleared.
+    let mut shared = Shared::default();
+    let mut children: Vec<Option<Vec<usize>>> =
+        objects.iter().map(|t| t.children(&mut shared)).collect();
+    let mut roots = Vec::<usize>::new();
+    env.trace(&mut |value| edges(value, &mut roots, &mut shared));
+    let mut addresses: Vec<usize> = objects.iter().map(|t| t.address()).collect();
+    let mut external: Vec<isize> = objects.iter().map(|t| t.strong_count() as isize).collect();
+    for (key, (strong, contents)) in shared.containers {
+        addresses.push(key);
+        external.push(strong as isize);
+        children.push(Some(contents));
+    }
+
+    let index: HashMap<usize, usize> = addresses
+        .iter()
+        .enumerate()
+        .map(|(i, &a)| (a, i))
+        .collect();
+    for child in children.iter().flatten().flatten() {
+        if let Some(&j) = index.get(child) {
+            external[j] -= 1;
+        }
+    }
+
+    let mut work: Vec<usize> = (0..addresses.len())
+        .filter(|&i| external[i] > 0 || children[i].is_none())
+        .collect();
+    work.extend(roots.iter().filter_map(|a| index.get(a)));
+
+    let mut marked = vec![false; addresses.len()];
+    while let Some(i) = work.pop() {
+        if marked[i] {
+            continue;
+        }
+        marked[i] = true;
+        if let Some(c) = &children[i] {
+            work.extend(c.iter().filter_map(|a| index.get(a)));
+        }
+    }
+
+    let mut freed = 0;
+    for (i, tracked) in objects.iter().enumerate() {
+        if !marked[i] {
+            tracked.clear();
+            freed += 1;
+        }
+    }
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        let survivors: Vec<Tracked> = objects.into_iter().filter(|t| t.strong_count() > 0).collect();
+        let mut objects = survivors;
+        objects.append(&mut heap.objects);
+        heap.objects = objects;
+        heap.pending = 0;
+        heap.threshold = INITIAL_THRESHOLD.max(heap.objects.len() * 2);
+        heap.collections += 1;
+        heap.freed += freed;
+    });
+    return freed;
+}
+
+// (name, value) pairs reported by the `gc_stats()` builtin.
+pub fn stats() -> Vec<(&'static str, usize)> {
+    return HEAP.with(|heap| {
+        let heap = heap.borrow();
+        let live = heap.objects.iter().filter(|t| t.strong_count() > 0).count();
+        vec![
+            ("live", live),
+            ("allocations", heap.allocations),
+            ("collections", heap.collections),
+            ("freed", heap.freed),
+        ]
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aurora::{interpreter::Interpreter, parser::Parser, scanner::Scanner};
+
+    fn run_stressed(script: &str) {
+        let tokens = Scanner::new(script.to_string()).scan_tokens();
+        let statements = Parser::new(tokens).parse();
+        let mut interpreter = Interpreter::new(statements);
+        interpreter.set_gc_stress(true);
+        let result = interpreter.try_interpret();
+        interpreter.set_gc_stress(false);
+        assert!(result.is_ok(), "{:?}", result.err());
+    }
+
+    // Two instances hold the same closure, so they share its captures. The
+    // captured `state` is only counted once against them, and stays live
+    // while the list literal holds it even though the instances are garbage.
+    #[test]
+    fn closure_shared_by_two_instances() {
+        run_stressed(
+            "
+            class Box {
+                function Box(v) {
+                    this.v = v;
+                }
+            }
+            class Holder {
+                function Holder(f) {
+                    this.f = f;
+                    this.other = nil;
+                }
+            }
+            function make() {
+                var state = Box(42);
+                function get() {
+                    return state;
+                }
+                return get;
+            }
+            function leak() {
+                var f = make();
+                var a = Holder(f);
+                var b = Holder(f);
+                a.other = b;
+                b.other = a;
+                return f();
+            }
+            var items = [leak(), Box(0)];
+            if (items[0].v != 42) {
+                items[0].missing;
+            }
+            ",
+        );
+    }
+}
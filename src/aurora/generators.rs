@@ -0,0 +1,450 @@
+use std::{cell::RefCell, fmt, mem, rc::Rc};
+
+use super::{
+    environment::{Environment, Memory, ScopeRef},
+    expressions::{call_method, find_method, Expression, FunctionType, Object},
+    gc,
+    patterns::Pattern,
+    statements::Statement,
+    token::Token,
+};
+
+// Work left to do in a suspended generator. The recursive `evaluate` cannot
+// stop half way through a statement, so generator bodies run from an explicit
+// stack of frames instead; expressions are still evaluated recursively, which
+// is why `yield` is only allowed as a statement. Frames share statements
+// with the function body, so resuming and looping copy no code.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Frame {
+    Run(Rc<Statement>),
+    // The rest of a block, starting at `next`.
+    Block {
+        statements: Rc<Vec<Statement>>,
+        next: usize,
+    },
+    PopScope,
+    While {
+        condition: Expression,
+        body: Rc<Statement>,
+    },
+    // The increment runs before each check of the condition but the first.
+    For {
+        condition: Option<Expression>,
+        increment: Option<Expression>,
+        body: Rc<Statement>,
+        first: bool,
+    },
+    ForIn {
+        cursor: Cursor,
+        pattern: Pattern,
+        body: Rc<Statement>,
+    },
+}
+
+#[derive(Clone, PartialEq, PartialOrd)]
+pub struct Generator {
+    name: Token,
+    // Scopes opened by the generator body, kept off the environment while
+    // the generator is suspended.
+    scopes: Vec<Memory>,
+    work: Vec<Frame>,
+    receiver: Option<(Object, Object)>,
+    // Where the generator function was defined, see `Environment::enter_function`.
+    home: ScopeRef,
+    running: bool,
+}
+
+impl Generator {
+    // Visits the values held by the suspended body.
+    pub fn trace(&self, visit: &mut dyn FnMut(&Object)) {
+        for scope in &self.scopes {
+            for value in scope.values() {
+                visit(value);
+            }
+        }
+        for frame in &self.work {
+            match frame {
+                Frame::ForIn {
+                    cursor: Cursor::Items(items, _),
+                    ..
+                } => {
+                    for item in items {
+                        visit(item);
+                    }
+                }
+                Frame::ForIn {
+                    cursor: Cursor::Iterator(iterator),
+                    ..
+                } => visit(iterator),
+                _ => (),
+            }
+        }
+        if let Some((receiver, class)) = &self.receiver {
+            visit(receiver);
+            visit(class);
+        }
+    }
+
+    // Empties the generator, as if its body had finished, and hands back
+    // what it held.
+    pub fn take_state(&mut self) -> (Vec<Memory>, Vec<Frame>, Option<(Object, Object)>) {
+        return (
+            mem::take(&mut self.scopes),
+            mem::take(&mut self.work),
+            self.receiver.take(),
+        );
+    }
+}
+
+impl fmt::Debug for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<generator {}>", self.name.lexeme)
+    }
+}
+
+// Calling a generator function only sets up its first scope; the body runs
+// on the first `next()`.
+pub fn create(
+    env: &Environment,
+    function: Object,
+    arguments: Vec<Object>,
+    receiver: Option<(Object, Object)>,
+) -> Object {
+    match function {
+        Object::FunctionObject {
+            name,
+            parameters,
+            body,
+            captures,
+            functype: _,
+            home,
+        } => {
+            if arguments.len() != parameters.len() {
+                panic!("Wrong Number of arguments for function {}", &name);
+            }
+            let mut scope = Memory::new();
+            for capture in captures.iter() {
+                scope.define(capture.0, capture.1.clone());
+            }
+            for (parameter, argument) in parameters.iter().zip(arguments) {
+                scope.define(*parameter, argument);
+            }
+            return Object::Generator(gc::alloc_generator(
+                env,
+                Generator {
+                    name,
+                    scopes: vec![scope],
+                    work: vec![Frame::Run(body)],
+                    receiver,
+                    home,
+                    running: false,
+                },
+            ));
+        }
+        _ => panic!("Object {:?} is not a function", function),
+    }
+}
+
+// Runs the generator until its next `yield`. Returns None once the body has
+// finished or returned.
+pub fn resume(env: &mut Environment, generator: &Rc<RefCell<Generator>>) -> Option<Object> {
+    let (name, mut scopes, mut work, receiver, home) = {
+        let mut state = generator.borrow_mut();
+        if state.running {
+            panic!("generator {} is already running", state.name.lexeme);
+        }
+        state.running = true;
+        (
+            state.name,
+            mem::take(&mut state.scopes),
+            mem::take(&mut state.work),
+            state.receiver.clone(),
+            state.home,
+        )
+    };
+
+    // Each resume is a call, so recursion through `next()` counts towards
+    // the depth limit like any other.
+    env.enter_call(&name);
+    let base = env.depth();
+    env.enter_function(home);
+    for scope in scopes.drain(..) {
+        env.stackpush(scope);
+    }
+    let bound = receiver.is_some();
+    match receiver {
+        Some((receiver, class)) => {
+            env.set_in_function(FunctionType::Method);
+            env.set_class_instance(receiver, class);
+        }
+        None => env.set_in_function(FunctionType::Function),
+    }
+
+    let result = run(env, &mut work);
+
+    if result.is_none() {
+        work.clear();
+    }
+    while env.depth() > base {
+        scopes.push(env.stackpop().unwrap());
+    }
+    env.exit_function();
+    scopes.reverse();
+    if bound {
+        env.clear_class_instance();
+    }
+    env.clear_in_function();
+    env.exit_call();
+
+    let mut state = generator.borrow_mut();
+    state.scopes = match result {
+        Some(_) => scopes,
+        None => Vec::<Memory>::new(),
+    };
+    state.work = work;
+    state.running = false;
+    return result;
+}
+
+fn run(env: &mut Environment, work: &mut Vec<Frame>) -> Option<Object> {
+    while let Some(frame) = work.pop() {
+        match frame {
+            Frame::Run(stmnt) => {
+                if let Some(result) = step(env, work, &stmnt) {
+                    return result;
+                }
+            }
+            Frame::Block { statements, next } => {
+                if next + 1 < statements.len() {
+                    work.push(Frame::Block {
+                        statements: statements.clone(),
+                        next: next + 1,
+                    });
+                }
+                if let Some(result) = step(env, work, &statements[next]) {
+                    return result;
+                }
+            }
+            Frame::PopScope => {
+                env.stackpop();
+            }
+            Frame::While { condition, body } => {
+                let vals = [Object::BoolObject(false), Object::NilObject];
+                if !vals.contains(&condition.evaluate(env)) {
+                    work.push(Frame::While {
+                        condition,
+                        body: body.clone(),
+                    });
+                    work.push(Frame::Run(body));
+                }
+            }
+            Frame::For {
+                condition,
+                increment,
+                body,
+                first,
+            } => {
+                if let (false, Some(expr)) = (first, &increment) {
+                    expr.evaluate(env);
+                }
+                let proceed = match &condition {
+                    Some(expr) => {
+                        let vals = [Object::BoolObject(false), Object::NilObject];
+                        !vals.contains(&expr.evaluate(env))
+                    }
+                    None => true,
+                };
+                if proceed {
+                    work.push(Frame::For {
+                        condition,
+                        increment,
+                        body: body.clone(),
+                        first: false,
+                    });
+                    work.push(Frame::Run(body));
+                }
+            }
+            Frame::ForIn {
+                mut cursor,
+                pattern,
+                body,
+            } => {
+                if let Some(value) = advance(env, &mut cursor) {
+                    env.stackpush(Memory::new());
+                    bind(env, &pattern, value);
+                    work.push(Frame::ForIn {
+                        cursor,
+                        pattern,
+                        body: body.clone(),
+                    });
+                    work.push(Frame::PopScope);
+                    work.push(Frame::Run(body));
+                }
+            }
+        }
+    }
+    return None;
+}
+
+// Runs one statement of the body, leaving frames for whatever it still has
+// to do. Returns Some when the body stops here: with the yielded value, or
+// with None on `return`.
+fn step(env: &mut Environment, work: &mut Vec<Frame>, stmnt: &Statement) -> Option<Option<Object>> {
+    match stmnt {
+        Statement::Yield { keyword: _, value } => {
+            return match value {
+                Some(expr) => Some(Some(expr.evaluate(env))),
+                None => Some(Some(Object::NilObject)),
+            };
+        }
+        Statement::Return { keyword: _, value } => {
+            if let Some(expr) = value {
+                expr.evaluate(env);
+            }
+            return Some(None);
+        }
+        Statement::Block { statements } => {
+            env.stackpush(Memory::new());
+            work.push(Frame::PopScope);
+            if !statements.is_empty() {
+                work.push(Frame::Block {
+                    statements: statements.clone(),
+                    next: 0,
+                });
+            }
+        }
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => match condition.evaluate(env) {
+            Object::BoolObject(true) => work.push(Frame::Run(then_branch.clone())),
+            Object::BoolObject(false) => {
+                if let Some(b) = else_branch {
+                    work.push(Frame::Run(b.clone()));
+                }
+            }
+            _ => panic!("Condition should be of type bool {:?}", condition),
+        },
+        Statement::While { condition, body } => work.push(Frame::While {
+            condition: condition.clone(),
+            body: body.clone(),
+        }),
+        Statement::For {
+            init,
+            condition,
+            increment,
+            body,
+        } => {
+            if let Some(s) = &**init {
+                s.evaluate(env);
+            }
+            work.push(Frame::For {
+                condition: condition.clone(),
+                increment: increment.clone(),
+                body: body.clone(),
+                first: true,
+            });
+        }
+        Statement::ForIn {
+            pattern,
+            keyword: _,
+            iterable,
+            body,
+        } => {
+            let value = iterable.evaluate(env);
+            work.push(Frame::ForIn {
+                cursor: cursor(env, value),
+                pattern: pattern.clone(),
+                body: body.clone(),
+            });
+        }
+        other => other.evaluate(env),
+    }
+    return None;
+}
+
+// Position in something a for-in loop walks over. Lists and strings are
+// walked directly; anything else goes through the `iter()`/`next()`
+// protocol, where `next()` returning nil ends the loop.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Cursor {
+    Items(Vec<Object>, usize),
+    Iterator(Object),
+}
+
+pub fn cursor(env: &mut Environment, value: Object) -> Cursor {
+    match value {
+        Object::ListObject(items) => return Cursor::Items(items, 0),
+        Object::StringObject(s) => {
+            return Cursor::Items(
+                s.chars().map(|c| Object::StringObject(c.to_string())).collect(),
+                0,
+            )
+        }
+        Object::Generator(_) => return Cursor::Iterator(value),
+        // `iter()` is called once: iterators usually return `this` from it.
+        Object::ClassInstance { .. } => match call_method(env, &value, "iter", vec![]) {
+            Some(iterator @ Object::ClassInstance { .. }) => return instance_cursor(iterator),
+            Some(
+                iterator @ (Object::ListObject(_) | Object::StringObject(_) | Object::Generator(_)),
+            ) => return cursor(env, iterator),
+            Some(other) => panic!("iter() returned {:?}, which is not iterable", other),
+            None => return instance_cursor(value),
+        },
+        other => panic!("Object {:?} is not iterable", other),
+    }
+}
+
+fn instance_cursor(iterator: Object) -> Cursor {
+    if find_method(&iterator, "next").is_none() {
+        panic!("Object {:?} has no next() method", iterator);
+    }
+    return Cursor::Iterator(iterator);
+}
+
+pub fn advance(env: &mut Environment, cursor: &mut Cursor) -> Option<Object> {
+    match cursor {
+        Cursor::Items(items, index) => {
+            let item = items.get(*index).cloned();
+            *index += 1;
+            return item;
+        }
+        Cursor::Iterator(Object::Generator(generator)) => return resume(env, generator),
+        Cursor::Iterator(iterator) => match call_method(env, iterator, "next", vec![]) {
+            Some(Object::NilObject) => return None,
+            Some(value) => return Some(value),
+            None => panic!("Object {:?} has no next() method", iterator),
+        },
+    }
+}
+
+pub fn bind(env: &mut Environment, pattern: &Pattern, value: Object) {
+    let mut bindings = Vec::<(Token, Object)>::new();
+    if !pattern.matches(&value, &mut bindings) {
+        panic!("Cannot destructure {:?} in for loop", value);
+    }
+    for (name, v) in bindings {
+        env.define(name, v);
+    }
+}
+
+// `next()` and `iter()` on generator objects.
+pub fn call_generator_method(
+    env: &mut Environment,
+    generator: &Rc<RefCell<Generator>>,
+    name: &Token,
+    arguments: Vec<Object>,
+) -> Object {
+    if !arguments.is_empty() {
+        panic!("Wrong Number of arguments for {} at {}", name.lexeme, name.line);
+    }
+    match name.lexeme.as_str() {
+        "next" => {
+            env.set_call_site(*name);
+            return resume(env, generator).unwrap_or(Object::NilObject);
+        }
+        "iter" => return Object::Generator(generator.clone()),
+        _ => panic!("Undefined method {} on generator at {}", name.lexeme, name.line),
+    }
+}
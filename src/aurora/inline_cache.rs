@@ -0,0 +1,149 @@
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    fmt,
+    rc::{Rc, Weak},
+};
+
+use super::{
+    environment::{Environment, Memory},
+    expressions::Object,
+    interner::Symbol,
+    token::Token,
+};
+
+// Cache for one property access site in the AST. Methods and setters are
+// looked up once per class: the site remembers the last class it saw and how
+// far up the superclass chain the member was defined. Fields live on each
+// instance, so for them the site remembers the slot the field was last found
+// in, which instances built by the same constructor share.
+//
+// Class members never change once the class is created, so a cached lookup
+// stays right for as long as the class it was made for is the one seen.
+#[derive(Clone)]
+pub struct InlineCache(Rc<RefCell<Entry>>);
+
+struct Entry {
+    // Weak, so the cache neither keeps the class alive nor lets another
+    // class be allocated at the same address while it is cached.
+    class: Weak<Environment>,
+    key: Symbol,
+    // Superclass links to follow to the class defining the member, or None
+    // when no class in the chain defines it.
+    hops: Option<usize>,
+    slot: usize,
+}
+
+impl InlineCache {
+    pub fn new() -> Self {
+        return InlineCache(Rc::new(RefCell::new(Entry {
+            class: Weak::new(),
+            key: Symbol::default(),
+            hops: None,
+            slot: 0,
+        })));
+    }
+
+    // Same result as `lookup_method(class, key(name))`. `key` turns the name
+    // at the site into the member looked up, and only runs on a miss.
+    pub fn lookup(
+        &self,
+        class: &Object,
+        name: &Token,
+        key: impl FnOnce(&Token) -> Token,
+    ) -> Option<(Object, Object)> {
+        let class_env = match class {
+            Object::Class { class_env, .. } => class_env,
+            _ => return None,
+        };
+        {
+            let entry = self.0.borrow();
+            if entry.class.as_ptr() == Rc::as_ptr(class_env) {
+                let key = Token {
+                    lexeme: entry.key,
+                    ..*name
+                };
+                return member_at(class, entry.hops?, key);
+            }
+        }
+
+        let key = key(name);
+        let mut hops = 0;
+        let mut current = class;
+        let found = loop {
+            match current {
+                Object::Class {
+                    class_env,
+                    superclass,
+                    ..
+                } => {
+                    if let Some(value) = class_env.find(key) {
+                        break Some((hops, (value, current.clone())));
+                    }
+                    match superclass {
+                        Some(s) => current = s,
+                        None => break None,
+                    }
+                }
+                _ => break None,
+            }
+            hops += 1;
+        };
+        let mut entry = self.0.borrow_mut();
+        entry.class = Rc::downgrade(class_env);
+        entry.key = key.lexeme;
+        entry.hops = found.as_ref().map(|(hops, _)| *hops);
+        return found.map(|(_, member)| member);
+    }
+
+    pub fn field(&self, memory: &Memory, name: Token) -> Option<Object> {
+        let hint = self.0.borrow().slot;
+        let (slot, value) = memory.get_hinted(name, hint)?;
+        self.0.borrow_mut().slot = slot;
+        return Some(value);
+    }
+
+    pub fn define_field(&self, memory: &mut Memory, name: Token, value: Object) {
+        let hint = self.0.borrow().slot;
+        let slot = memory.define_hinted(name, value, hint);
+        self.0.borrow_mut().slot = slot;
+    }
+}
+
+fn member_at(class: &Object, hops: usize, key: Token) -> Option<(Object, Object)> {
+    let mut current = class;
+    for _ in 0..hops {
+        match current {
+            Object::Class {
+                superclass: Some(s),
+                ..
+            } => current = s,
+            _ => return None,
+        }
+    }
+    match current {
+        Object::Class { class_env, .. } => {
+            return class_env.find(key).map(|value| (value, current.clone()))
+        }
+        _ => return None,
+    }
+}
+
+// Caches are not part of what an expression means.
+impl PartialEq for InlineCache {
+    fn eq(&self, _: &Self) -> bool {
+        return true;
+    }
+}
+
+impl PartialOrd for InlineCache {
+    fn partial_cmp(&self, _: &Self) -> Option<Ordering> {
+        return Some(Ordering::Equal);
+    }
+}
+
+impl fmt::Debug for InlineCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InlineCache")
+    }
+}
@@ -0,0 +1,83 @@
+use std::{cell::RefCell, collections::HashMap, fmt};
+
+// Identifiers and string literals are interned once by the scanner, so tokens
+// can be copied freely and scopes compare names as integers instead of
+// hashing and comparing strings on every lookup.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+// Interned text lives for the rest of the program; the set of names in a
+// script is small and fixed once it has been scanned. Strings made at run
+// time go through `Symbol::lookup` so they never grow it.
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+        ids: HashMap::new(),
+        names: Vec::new(),
+    });
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        return INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&symbol) = interner.ids.get(name) {
+                return symbol;
+            }
+            let symbol = Symbol(interner.names.len() as u32);
+            let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+            interner.names.push(name);
+            interner.ids.insert(name, symbol);
+            symbol
+        });
+    }
+
+    // The symbol for `name` if it has been interned. A name that never was
+    // cannot be bound anywhere.
+    pub fn lookup(name: &str) -> Option<Symbol> {
+        return INTERNER.with(|interner| interner.borrow().ids.get(name).copied());
+    }
+
+    // Dense, starting at 0, so symbols can index tables directly.
+    pub fn index(&self) -> usize {
+        return self.0 as usize;
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        return INTERNER.with(|interner| interner.borrow().names[self.0 as usize]);
+    }
+}
+
+impl Default for Symbol {
+    fn default() -> Self {
+        return Symbol::intern("");
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        return self.as_str() == other;
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        return self.as_str() == *other;
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
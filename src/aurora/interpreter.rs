@@ -1,20 +1,89 @@
-use super::{statements::Statement, environment::Environment};
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+
+use super::{statements::Statement, environment::Environment, gc, scheduler::Scheduler, token::Token};
 
 pub struct Interpreter {
     statments: Vec<Statement>,
-    env: Environment
+    env: Environment,
+    scheduler: Scheduler,
+}
+
+// Errors raised while running a script that embedders can handle themselves,
+// see `Interpreter::try_interpret`. Other runtime errors are still panics.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    StackOverflow {
+        limit: usize,
+        // Innermost call first, each with the line it was called from.
+        frames: Vec<Token>,
+    },
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::StackOverflow { limit, frames } => {
+                write!(f, "StackOverflow: maximum call depth of {} exceeded", limit)?;
+                for frame in frames {
+                    write!(f, "\n    at {} (line {})", frame.lexeme, frame.line)?;
+                }
+                return Ok(());
+            }
+        }
+    }
 }
 
 impl Interpreter {
     pub fn new(statments: Vec<Statement> ) -> Self {
         let mut env = Environment::new();
         env.global();
-        return Interpreter { statments, env }
+        return Interpreter { statments, env, scheduler: Scheduler::new() }
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.env.set_max_depth(max_depth);
+    }
+
+    // Collect garbage on every allocation. Slow; meant for testing.
+    pub fn set_gc_stress(&mut self, stress: bool) {
+        gc::set_stress(stress);
     }
 
+    // Runs the script, then any tasks it spawned until they have all finished.
     pub fn interpret(&mut self) {
-        for stmt in self.statments.clone() {
+        for stmt in self.statments.iter() {
             stmt.evaluate(&mut self.env)
         }
+        self.scheduler.run(&mut self.env);
+    }
+
+    // Like `interpret`, but returns a `RuntimeError` instead of unwinding.
+    // The environment is reset to its globals when that happens.
+    pub fn try_interpret(&mut self) -> Result<(), RuntimeError> {
+        let previous: Arc<dyn Fn(&panic::PanicHookInfo) + Sync + Send> = Arc::from(panic::take_hook());
+        let hook = previous.clone();
+        panic::set_hook(Box::new(move |info| {
+            if info.payload().downcast_ref::<RuntimeError>().is_none() {
+                hook(info);
+            }
+        }));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.interpret()));
+        let _ = panic::take_hook();
+        panic::set_hook(Box::new(move |info| previous(info)));
+
+        match result {
+            Ok(()) => return Ok(()),
+            Err(payload) => match payload.downcast::<RuntimeError>() {
+                Ok(error) => {
+                    self.env.unwind();
+                    return Err(*error);
+                }
+                Err(payload) => panic::resume_unwind(payload),
+            },
+        }
     }
-}
\ No newline at end of file
+}
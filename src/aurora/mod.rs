@@ -4,4 +4,15 @@ pub mod parser;
 pub mod expressions;
 pub mod statements;
 pub mod interpreter;
-pub mod environment;
\ No newline at end of file
+pub mod resolver;
+pub mod environment;
+pub mod builtins;
+pub mod patterns;
+pub mod generators;
+pub mod scheduler;
+pub mod gc;
+pub mod inline_cache;
+pub mod interner;
+pub mod optimizer;
+pub mod serialize;
+pub mod dump;
\ No newline at end of file
@@ -0,0 +1,433 @@
+use std::rc::Rc;
+
+use super::{
+    expressions::{Expression, Object},
+    patterns::MatchArm,
+    statements::Statement,
+    token::{Token, TokenType},
+};
+
+// Optimisation pass run on the resolved AST before it is interpreted. It
+// folds operators applied to literals and drops branches and loops whose
+// condition is a literal that can never let them run. Only cases whose
+// result is known without running anything are rewritten, so a script
+// behaves the same with or without the pass, including the errors it raises.
+pub fn optimize(statements: Vec<Statement>) -> Vec<Statement> {
+    return block(statements);
+}
+
+// Optimises a list of statements, dropping those that can never run.
+fn block(statements: Vec<Statement>) -> Vec<Statement> {
+    let mut out = Vec::<Statement>::new();
+    for stmnt in statements {
+        let returns = matches!(stmnt, Statement::Return { .. });
+        if let Some(s) = statement(stmnt) {
+            out.push(s);
+        }
+        // Nothing after a `return` in the same block runs.
+        if returns {
+            break;
+        }
+    }
+    return out;
+}
+
+// Like `statement`, for places that need a statement even when it does
+// nothing.
+fn required(stmnt: Statement) -> Statement {
+    match statement(stmnt) {
+        Some(s) => return s,
+        None => {
+            return Statement::Block {
+                statements: Rc::new(vec![]),
+            }
+        }
+    }
+}
+
+// Takes a statement out of its Rc, copying it only if it is still shared.
+fn owned<T: Clone>(shared: Rc<T>) -> T {
+    return Rc::try_unwrap(shared).unwrap_or_else(|shared| (*shared).clone());
+}
+
+fn required_shared(stmnt: Rc<Statement>) -> Rc<Statement> {
+    return Rc::new(required(owned(stmnt)));
+}
+
+// Returns None when the statement can be left out entirely.
+fn statement(stmnt: Statement) -> Option<Statement> {
+    match stmnt {
+        Statement::Block { statements } => {
+            return Some(Statement::Block {
+                statements: Rc::new(block(owned(statements))),
+            })
+        }
+        Statement::Class {
+            name,
+            superclass,
+            traits,
+            methods,
+            statics,
+        } => {
+            return Some(Statement::Class {
+                name,
+                superclass,
+                traits,
+                methods: methods.into_iter().map(required).collect(),
+                statics: statics.into_iter().map(required).collect(),
+            })
+        }
+        Statement::Trait {
+            name,
+            required: names,
+            methods,
+        } => {
+            return Some(Statement::Trait {
+                name,
+                required: names,
+                methods: methods.into_iter().map(required).collect(),
+            })
+        }
+        Statement::Expression { expression } => {
+            return Some(Statement::Expression {
+                expression: fold(expression),
+            })
+        }
+        Statement::Function {
+            name,
+            params,
+            body,
+            functype,
+        } => {
+            return Some(Statement::Function {
+                name,
+                params,
+                body: required_shared(body),
+                functype,
+            });
+        }
+        Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            let condition = fold(condition);
+            match condition {
+                Expression::Literal {
+                    value: Object::BoolObject(true),
+                } => return statement(owned(then_branch)),
+                Expression::Literal {
+                    value: Object::BoolObject(false),
+                } => match else_branch {
+                    Some(b) => return statement(owned(b)),
+                    None => return None,
+                },
+                condition => {
+                    return Some(Statement::If {
+                        condition,
+                        then_branch: required_shared(then_branch),
+                        else_branch: else_branch.map(required_shared),
+                    })
+                }
+            }
+        }
+        Statement::Print { expression } => {
+            return Some(Statement::Print {
+                expression: fold(expression),
+            })
+        }
+        Statement::Return { keyword, value } => {
+            return Some(Statement::Return {
+                keyword,
+                value: value.map(fold),
+            })
+        }
+        Statement::Variable { name, init } => {
+            return Some(Statement::Variable {
+                name,
+                init: init.map(fold),
+            })
+        }
+        Statement::Constant { name, init } => {
+            return Some(Statement::Constant {
+                name,
+                init: fold(init),
+            })
+        }
+        Statement::Destructure {
+            pattern,
+            equals,
+            init,
+        } => {
+            return Some(Statement::Destructure {
+                pattern,
+                equals,
+                init: fold(init),
+            })
+        }
+        Statement::While { condition, body } => {
+            let condition = fold(condition);
+            match condition {
+                Expression::Literal {
+                    value: Object::BoolObject(false) | Object::NilObject,
+                } => return None,
+                condition => {
+                    return Some(Statement::While {
+                        condition,
+                        body: required_shared(body),
+                    })
+                }
+            }
+        }
+        Statement::For {
+            init,
+            condition,
+            increment,
+            body,
+        } => {
+            return Some(Statement::For {
+                init: Box::new(init.and_then(statement)),
+                condition: condition.map(fold),
+                increment: increment.map(fold),
+                body: required_shared(body),
+            })
+        }
+        Statement::ForIn {
+            pattern,
+            keyword,
+            iterable,
+            body,
+        } => {
+            return Some(Statement::ForIn {
+                pattern,
+                keyword,
+                iterable: fold(iterable),
+                body: required_shared(body),
+            })
+        }
+        Statement::Yield { keyword, value } => {
+            return Some(Statement::Yield {
+                keyword,
+                value: value.map(fold),
+            })
+        }
+        stmnt @ Statement::Enum { .. } => return Some(stmnt),
+    }
+}
+
+// Folds the expression in place, reusing its allocation.
+fn fold_box(mut expr: Box<Expression>) -> Box<Expression> {
+    *expr = fold(*expr);
+    return expr;
+}
+
+fn fold(expr: Expression) -> Expression {
+    match expr {
+        Expression::Assign { name, value } => {
+            return Expression::Assign {
+                name,
+                value: fold_box(value),
+            }
+        }
+        Expression::AssignPattern {
+            pattern,
+            equals,
+            value,
+        } => {
+            return Expression::AssignPattern {
+                pattern,
+                equals,
+                value: fold_box(value),
+            }
+        }
+        Expression::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let left = fold(*left);
+            let right = fold(*right);
+            if let (Expression::Literal { value: l }, Expression::Literal { value: r }) =
+                (&left, &right)
+            {
+                if let Some(value) = binary(&operator, l, r) {
+                    return Expression::Literal { value };
+                }
+            }
+            return Expression::Binary {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        Expression::Call {
+            callee,
+            paren,
+            arguments,
+        } => {
+            return Expression::Call {
+                callee: fold_box(callee),
+                paren,
+                arguments: arguments.into_iter().map(fold).collect(),
+            }
+        }
+        Expression::Get {
+            object,
+            name,
+            cache,
+        } => {
+            return Expression::Get {
+                object: fold_box(object),
+                name,
+                cache,
+            }
+        }
+        Expression::Grouping { expression } => match fold(*expression) {
+            literal @ Expression::Literal { .. } => return literal,
+            expression => {
+                return Expression::Grouping {
+                    expression: Box::new(expression),
+                }
+            }
+        },
+        Expression::Index {
+            object,
+            bracket,
+            index,
+        } => {
+            return Expression::Index {
+                object: fold_box(object),
+                bracket,
+                index: fold_box(index),
+            }
+        }
+        Expression::List { elements } => {
+            return Expression::List {
+                elements: elements.into_iter().map(fold).collect(),
+            }
+        }
+        Expression::Match {
+            keyword,
+            subject,
+            arms,
+        } => {
+            return Expression::Match {
+                keyword,
+                subject: fold_box(subject),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| MatchArm {
+                        pattern: arm.pattern,
+                        guard: arm.guard.map(fold),
+                        arrow: arm.arrow,
+                        body: fold(arm.body),
+                    })
+                    .collect(),
+            }
+        }
+        Expression::Logical {
+            left,
+            operator,
+            right,
+        } => {
+            return Expression::Logical {
+                left: fold_box(left),
+                operator,
+                right: fold_box(right),
+            }
+        }
+        Expression::Set {
+            object,
+            name,
+            value,
+            cache,
+        } => {
+            return Expression::Set {
+                object: fold_box(object),
+                name,
+                value: fold_box(value),
+                cache,
+            }
+        }
+        Expression::Unary { operator, right } => {
+            let right = fold(*right);
+            if let Expression::Literal { value } = &right {
+                if let Some(value) = unary(&operator, value) {
+                    return Expression::Literal { value };
+                }
+            }
+            return Expression::Unary {
+                operator,
+                right: Box::new(right),
+            };
+        }
+        expr @ (Expression::Literal { .. }
+        | Expression::Super { .. }
+        | Expression::This { .. }
+        | Expression::Variable { .. }) => return expr,
+    }
+}
+
+// The value `left op right` evaluates to, when the interpreter would compute
+// it without raising an error. Bitwise operators are left to run time.
+fn binary(op: &Token, left: &Object, right: &Object) -> Option<Object> {
+    match (op.tokentype, left, right) {
+        (TokenType::Plus, Object::NumberObject(x), Object::NumberObject(y)) => {
+            return Some(Object::NumberObject(x + y))
+        }
+        (TokenType::Plus, Object::StringObject(x), Object::StringObject(y)) => {
+            return Some(Object::StringObject(format!("{x}{y}")))
+        }
+        (TokenType::Minus, Object::NumberObject(x), Object::NumberObject(y)) => {
+            return Some(Object::NumberObject(x - y))
+        }
+        (TokenType::Star, Object::NumberObject(x), Object::NumberObject(y)) => {
+            return Some(Object::NumberObject(x * y))
+        }
+        (TokenType::Slash, Object::NumberObject(x), Object::NumberObject(y)) => {
+            return Some(Object::NumberObject(x / y))
+        }
+        (TokenType::Greater, Object::NumberObject(x), Object::NumberObject(y)) => {
+            return Some(Object::BoolObject(x > y))
+        }
+        (TokenType::GreaterEqual, Object::NumberObject(x), Object::NumberObject(y)) => {
+            return Some(Object::BoolObject(x >= y))
+        }
+        (TokenType::Less, Object::NumberObject(x), Object::NumberObject(y)) => {
+            return Some(Object::BoolObject(x < y))
+        }
+        (TokenType::LessEqual, Object::NumberObject(x), Object::NumberObject(y)) => {
+            return Some(Object::BoolObject(x <= y))
+        }
+        // Comparing values of different types is an error.
+        (TokenType::EqualEqual | TokenType::BangEqual, _, _) if is_scalar(left) => {
+            if std::mem::discriminant(left) != std::mem::discriminant(right) {
+                return None;
+            }
+            let equal = left == right;
+            return Some(Object::BoolObject(match op.tokentype {
+                TokenType::EqualEqual => equal,
+                _ => !equal,
+            }));
+        }
+        _ => return None,
+    }
+}
+
+fn unary(op: &Token, right: &Object) -> Option<Object> {
+    match (op.tokentype, right) {
+        (TokenType::Minus, Object::NumberObject(x)) => return Some(Object::NumberObject(-x)),
+        (TokenType::Bang, Object::BoolObject(x)) => return Some(Object::BoolObject(!x)),
+        _ => return None,
+    }
+}
+
+fn is_scalar(value: &Object) -> bool {
+    match value {
+        Object::NumberObject(_)
+        | Object::StringObject(_)
+        | Object::BoolObject(_)
+        | Object::NilObject => return true,
+        _ => return false,
+    }
+}
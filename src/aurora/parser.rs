@@ -1,6 +1,10 @@
+use std::rc::Rc;
+
 use crate::aurora::token;
 
 use super::expressions::FunctionType;
+use super::inline_cache::InlineCache;
+use super::patterns::{MatchArm, Pattern};
 use super::statements;
 use super::{expressions::Expression, expressions::Object, statements::Statement};
 
@@ -36,7 +40,7 @@ impl Parser {
         return tokens.into_iter().nth(self.current - 1).unwrap();
     }
     fn at_end(&self) -> bool {
-        return self.peek().tokentype.clone() == token::TokenType::Eof;
+        return self.peek().tokentype == token::TokenType::Eof;
     }
     fn check(&self, tokentype: TokenType) -> bool {
         if self.at_end() {
@@ -44,6 +48,13 @@ impl Parser {
         }
         return self.peek().tokentype == tokentype;
     }
+    // `get` and `set` are only keywords at the start of a class member.
+    fn check_accessor(&self, lexeme: &str) -> bool {
+        return self.check(TokenType::Identifier)
+            && self.peek().lexeme == lexeme
+            && self.tokens[self.current + 1].tokentype == TokenType::Identifier;
+    }
+
     fn matches(&mut self, tokentypes: Vec<token::TokenType>) -> bool {
         for tokentype in tokentypes {
             if self.check(tokentype) {
@@ -59,6 +70,17 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Expression {
+        if self.at_destructuring_assignment() {
+            let pattern = self.pattern();
+            let equals = self.consume(TokenType::Equal, "expected = after destructuring pattern");
+            self.check_assignable(&pattern, &equals);
+            let value = self.assignment();
+            return Expression::AssignPattern {
+                pattern,
+                equals,
+                value: Box::new(value),
+            };
+        }
         let expr = self.or();
 
         if self.matches(Vec::from([TokenType::Equal])) {
@@ -71,11 +93,12 @@ impl Parser {
                         value: Box::new(value),
                     }
                 }
-                Expression::Get { object, name } => {
+                Expression::Get { object, name, .. } => {
                     return Expression::Set {
                         object: object.clone(),
-                        name: name.clone(),
+                        name,
                         value: Box::new(value),
+                        cache: InlineCache::new(),
                     }
                 }
                 _ => panic!("Invalid assignment {:?}", self.previous()),
@@ -84,6 +107,58 @@ impl Parser {
         return expr;
     }
 
+    // Whether the next tokens are `[...] =` or `{...} =`, the start of a
+    // destructuring assignment such as `[a, b] = [b, a];`. The brackets are
+    // only matched up here; `pattern` parses what is inside them.
+    fn at_destructuring_assignment(&self) -> bool {
+        if !self.check(TokenType::LeftBracket) && !self.check(TokenType::LeftBrace) {
+            return false;
+        }
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(self.current) {
+            match token.tokentype {
+                TokenType::LeftBracket | TokenType::LeftBrace | TokenType::LeftParen => depth += 1,
+                TokenType::RightBracket | TokenType::RightBrace | TokenType::RightParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return match self.tokens.get(i + 1) {
+                            Some(next) => next.tokentype == TokenType::Equal,
+                            None => false,
+                        };
+                    }
+                }
+                TokenType::SemiColon | TokenType::Eof => return false,
+                _ => (),
+            }
+        }
+        return false;
+    }
+
+    // Assignments can only store into names, so patterns that test a value
+    // rather than bind it are rejected.
+    fn check_assignable(&self, pattern: &Pattern, equals: &Token) {
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding(_) => (),
+            Pattern::List { elements, rest } => {
+                for element in elements {
+                    self.check_assignable(element, equals);
+                }
+                if let Some(rest) = rest {
+                    self.check_assignable(rest, equals);
+                }
+            }
+            Pattern::Map(entries) => {
+                for (_, value) in entries {
+                    self.check_assignable(value, equals);
+                }
+            }
+            other => panic!(
+                "Invalid destructuring target {:?} at line {}",
+                other, equals.line
+            ),
+        }
+    }
+
     fn or(&mut self) -> Expression {
         let mut expr = self.and();
 
@@ -101,11 +176,11 @@ impl Parser {
     }
 
     fn and(&mut self) -> Expression {
-        let mut expr = self.equality();
+        let mut expr = self.bit_or();
 
         while self.matches(Vec::from([TokenType::And])) {
             let operator = self.previous();
-            let right = self.equality();
+            let right = self.bit_or();
             expr = Expression::Logical {
                 left: Box::new(expr),
                 operator,
@@ -116,6 +191,51 @@ impl Parser {
         return expr;
     }
 
+    fn bit_or(&mut self) -> Expression {
+        let mut expr = self.bit_xor();
+
+        while self.matches(Vec::from([TokenType::Pipe])) {
+            let operator = self.previous();
+            let right = self.bit_xor();
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        return expr;
+    }
+
+    fn bit_xor(&mut self) -> Expression {
+        let mut expr = self.bit_and();
+
+        while self.matches(Vec::from([TokenType::Caret])) {
+            let operator = self.previous();
+            let right = self.bit_and();
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        return expr;
+    }
+
+    fn bit_and(&mut self) -> Expression {
+        let mut expr = self.equality();
+
+        while self.matches(Vec::from([TokenType::Ampersand])) {
+            let operator = self.previous();
+            let right = self.equality();
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        return expr;
+    }
+
     fn equality(&mut self) -> Expression {
         let mut expr = self.comparison();
 
@@ -132,16 +252,17 @@ impl Parser {
     }
 
     fn comparison(&mut self) -> Expression {
-        let mut expr = self.term();
+        let mut expr = self.shift();
 
         while self.matches(Vec::from([
             TokenType::Greater,
             TokenType::GreaterEqual,
             TokenType::Less,
             TokenType::LessEqual,
+            TokenType::Is,
         ])) {
             let operator = self.previous();
-            let right = self.term();
+            let right = self.shift();
             expr = Expression::Binary {
                 left: Box::new(expr.clone()),
                 operator: operator,
@@ -151,6 +272,21 @@ impl Parser {
         return expr;
     }
 
+    fn shift(&mut self) -> Expression {
+        let mut expr = self.term();
+
+        while self.matches(Vec::from([TokenType::LessLess, TokenType::GreaterGreater])) {
+            let operator = self.previous();
+            let right = self.term();
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+        return expr;
+    }
+
     fn term(&mut self) -> Expression {
         let mut expr = self.factor();
 
@@ -182,7 +318,7 @@ impl Parser {
     }
 
     fn unary(&mut self) -> Expression {
-        if self.matches(Vec::from([TokenType::Bang, TokenType::Minus])) {
+        if self.matches(Vec::from([TokenType::Bang, TokenType::Minus, TokenType::Tilde])) {
             let operator = self.previous();
             let right = self.unary();
             return Expression::Unary {
@@ -204,6 +340,15 @@ impl Parser {
                 expr = Expression::Get {
                     object: Box::new(expr),
                     name,
+                    cache: InlineCache::new(),
+                }
+            } else if self.matches(vec![TokenType::LeftBracket]) {
+                let index = self.expression();
+                let bracket = self.consume(TokenType::RightBracket, "expect ']' after index");
+                expr = Expression::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
                 }
             } else {
                 break;
@@ -256,9 +401,9 @@ impl Parser {
         if self.matches(Vec::from([TokenType::Number, TokenType::String])) {
             return Expression::Literal {
                 value: match self.previous().tokentype {
-                    TokenType::String => Object::StringObject(self.previous().literal),
+                    TokenType::String => Object::StringObject(self.previous().literal.to_string()),
                     TokenType::Number => {
-                        Object::NumberObject(self.previous().literal.parse::<f64>().unwrap())
+                        Object::NumberObject(self.previous().literal.as_str().parse::<f64>().unwrap())
                     }
                     _ => {
                         panic!("Token Not Number or String! {}", self.previous())
@@ -266,6 +411,20 @@ impl Parser {
                 },
             };
         }
+        if self.matches(vec![TokenType::LeftBracket]) {
+            let mut elements = Vec::<Expression>::new();
+            if !self.check(TokenType::RightBracket) {
+                elements.push(self.expression());
+                while self.matches(vec![TokenType::Comma]) {
+                    elements.push(self.expression());
+                }
+            }
+            self.consume(TokenType::RightBracket, "expect ']' after list elements");
+            return Expression::List { elements };
+        }
+        if self.matches(vec![TokenType::Match]) {
+            return self.match_expression();
+        }
         if self.matches(vec![TokenType::This]) {
             return Expression::This {
                 keyword: self.previous(),
@@ -284,7 +443,7 @@ impl Parser {
             };
         }
         if self.matches(Vec::from([TokenType::Super])) {
-            let keyword = self.previous().clone();
+            let keyword = self.previous();
             self.consume(TokenType::Dot, "Expected dot after Super keyword");
             let method = self.consume(
                 TokenType::Identifier,
@@ -298,8 +457,125 @@ impl Parser {
         )
     }
 
+    fn match_expression(&mut self) -> Expression {
+        let keyword = self.previous();
+        self.consume(TokenType::LeftParen, "Expected ( after match");
+        let subject = self.expression();
+        self.consume(TokenType::RightParen, "Expected ) after match value");
+        self.consume(TokenType::LeftBrace, "Expected { before match arms");
+
+        let mut arms = Vec::<MatchArm>::new();
+        while !self.check(TokenType::RightBrace) && !self.at_end() {
+            let pattern = self.pattern();
+            let guard = match self.matches(vec![TokenType::If]) {
+                true => Some(self.expression()),
+                false => None,
+            };
+            let arrow = self.consume(TokenType::FatArrow, "Expected => after match pattern");
+            let body = self.expression();
+            arms.push(MatchArm {
+                pattern,
+                guard,
+                arrow,
+                body,
+            });
+            if !self.matches(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expected } after match arms");
+        return Expression::Match {
+            keyword,
+            subject: Box::new(subject),
+            arms,
+        };
+    }
+
+    fn pattern(&mut self) -> Pattern {
+        if self.matches(vec![TokenType::Minus]) {
+            let number = self.consume(TokenType::Number, "Expected number after - in pattern");
+            return Pattern::Literal(Object::NumberObject(
+                -number.literal.as_str().parse::<f64>().unwrap(),
+            ));
+        }
+        if self.matches(vec![
+            TokenType::Number,
+            TokenType::String,
+            TokenType::True,
+            TokenType::False,
+            TokenType::Nil,
+        ]) {
+            let token = self.previous();
+            return Pattern::Literal(match token.tokentype {
+                TokenType::Number => Object::NumberObject(token.literal.as_str().parse::<f64>().unwrap()),
+                TokenType::String => Object::StringObject(token.literal.to_string()),
+                TokenType::True => Object::BoolObject(true),
+                TokenType::False => Object::BoolObject(false),
+                _ => Object::NilObject,
+            });
+        }
+        if self.matches(vec![TokenType::LeftBracket]) {
+            let mut elements = Vec::<Pattern>::new();
+            let mut rest = None;
+            while !self.check(TokenType::RightBracket) && !self.at_end() {
+                if self.matches(vec![TokenType::DotDotDot]) {
+                    rest = Some(Box::new(self.pattern()));
+                    break;
+                }
+                elements.push(self.pattern());
+                if !self.matches(vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+            self.consume(TokenType::RightBracket, "expect ']' after list pattern");
+            return Pattern::List { elements, rest };
+        }
+        if self.matches(vec![TokenType::LeftBrace]) {
+            let mut entries = Vec::<(Token, Pattern)>::new();
+            if !self.check(TokenType::RightBrace) {
+                loop {
+                    let key = self.consume(TokenType::Identifier, "Expected field name in pattern");
+                    let value = match self.matches(vec![TokenType::Colon]) {
+                        true => self.pattern(),
+                        false => Pattern::Binding(key),
+                    };
+                    entries.push((key, value));
+                    if !self.matches(vec![TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBrace, "expect '}' after map pattern");
+            return Pattern::Map(entries);
+        }
+        let name = self.consume(TokenType::Identifier, "Expected pattern");
+        if self.matches(vec![TokenType::Dot]) {
+            let tag = self.consume(TokenType::Identifier, "Expected variant name in pattern");
+            let mut fields = Vec::<Pattern>::new();
+            if self.matches(vec![TokenType::LeftParen]) {
+                if !self.check(TokenType::RightParen) {
+                    fields.push(self.pattern());
+                    while self.matches(vec![TokenType::Comma]) {
+                        fields.push(self.pattern());
+                    }
+                }
+                self.consume(TokenType::RightParen, "expect ) after variant pattern");
+            }
+            return Pattern::Variant {
+                enum_name: name,
+                tag,
+                fields,
+            };
+        }
+        if name.lexeme == "_" {
+            return Pattern::Wildcard;
+        }
+        return Pattern::Binding(name);
+    }
+
     fn consume(&mut self, tokentype: TokenType, message: &str) -> token::Token {
-        if self.check(tokentype.clone()) {
+        if self.check(tokentype) {
             return self.advance();
         }
         panic!(
@@ -320,8 +596,11 @@ impl Parser {
 
             match self.peek().tokentype {
                 TokenType::Class
+                | TokenType::Trait
+                | TokenType::Enum
                 | TokenType::Fun
                 | TokenType::Var
+                | TokenType::Const
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
@@ -351,7 +630,7 @@ impl Parser {
     fn statement(&mut self) -> Statement {
         if self.matches(Vec::<TokenType>::from([TokenType::Print])) {
             return self.print_statement();
-        } else if self.check(TokenType::LeftBrace) {
+        } else if self.check(TokenType::LeftBrace) && !self.at_destructuring_assignment() {
             return self.block();
         } else if self.matches(Vec::<TokenType>::from([TokenType::If])) {
             return self.if_statement();
@@ -361,11 +640,25 @@ impl Parser {
             return self.for_statement();
         } else if self.matches(Vec::<TokenType>::from([TokenType::Return])) {
             return self.return_statement();
+        } else if self.matches(Vec::<TokenType>::from([TokenType::Yield])) {
+            return self.yield_statement();
         }
 
         return self.expr_statement();
     }
 
+    fn yield_statement(&mut self) -> Statement {
+        let keyword = self.previous();
+
+        let mut value = None;
+        if !self.check(TokenType::SemiColon) {
+            value = Some(self.expression());
+        }
+
+        self.consume(TokenType::SemiColon, "expected semicolon after yield");
+        return Statement::Yield { keyword, value };
+    }
+
     fn return_statement(&mut self) -> Statement {
         let keyword = self.previous();
 
@@ -388,6 +681,14 @@ impl Parser {
                 format!("expect function keyword {:#?} for", &functype).as_str(),
             );
         }
+        let functype = match functype {
+            FunctionType::Function | FunctionType::Method
+                if self.matches(vec![TokenType::Star]) =>
+            {
+                FunctionType::Generator
+            }
+            other => other,
+        };
 
         let name = self.consume(
             TokenType::Identifier,
@@ -422,18 +723,39 @@ impl Parser {
             TokenType::RightParen,
             format!("expect ) after {:#?} params", &functype).as_str(),
         );
+        match functype {
+            FunctionType::Getter if !params.is_empty() => {
+                panic!("getter {} cannot take parameters", name)
+            }
+            FunctionType::Setter if params.len() != 1 => {
+                panic!("setter {} must take exactly one parameter", name)
+            }
+            _ => (),
+        }
 
         let body = self.block();
         return Statement::Function {
             name,
-            params,
-            body: Box::new(body),
+            params: Rc::new(params),
+            body: Rc::new(body),
             functype,
         };
     }
 
     fn for_statement(&mut self) -> Statement {
         self.consume(TokenType::LeftParen, "expect '(' after 'if'");
+        if let Some(pattern) = self.for_in_target() {
+            let keyword = self.consume(TokenType::In, "expect 'in' after loop variable");
+            let iterable = self.expression();
+            self.consume(TokenType::RightParen, "expect ')' after for-in clause");
+            let body = self.block();
+            return Statement::ForIn {
+                pattern,
+                keyword,
+                iterable,
+                body: Rc::new(body),
+            };
+        }
         let init;
         if self.matches(vec![TokenType::SemiColon]) {
             init = None;
@@ -463,10 +785,26 @@ impl Parser {
             init: Box::new(init),
             condition,
             increment,
-            body: Box::new(body),
+            body: Rc::new(body),
         };
     }
 
+    // Looks ahead for `x in`, `var x in` or `[a, b] in`; leaves the position
+    // untouched when the loop is a C-style one.
+    fn for_in_target(&mut self) -> Option<Pattern> {
+        let checkpoint = self.current;
+        self.matches(vec![TokenType::Var]);
+        let pattern = match self.peek().tokentype {
+            TokenType::Identifier | TokenType::LeftBracket | TokenType::LeftBrace => self.pattern(),
+            _ => Pattern::Wildcard,
+        };
+        if self.check(TokenType::In) {
+            return Some(pattern);
+        }
+        self.current = checkpoint;
+        return None;
+    }
+
     fn while_statment(&mut self) -> Statement {
         self.consume(TokenType::LeftParen, "expect '(' after 'if'");
         let condition = self.expression();
@@ -475,7 +813,7 @@ impl Parser {
 
         return Statement::While {
             condition,
-            body: Box::new(body),
+            body: Rc::new(body),
         };
     }
 
@@ -484,9 +822,9 @@ impl Parser {
         let condition = self.expression();
         self.consume(TokenType::RightParen, "expect ')' after 'if'");
 
-        let then_branch = Box::new(self.block());
+        let then_branch = Rc::new(self.block());
         let else_branch = match self.matches(Vec::<TokenType>::from([TokenType::Else])) {
-            true => Some(Box::new(self.block())),
+            true => Some(Rc::new(self.block())),
             false => None,
         };
 
@@ -507,16 +845,24 @@ impl Parser {
 
         self.consume(TokenType::RightBrace, "expect '}' after block");
 
-        return Statement::Block { statements: stmnts };
+        return Statement::Block {
+            statements: Rc::new(stmnts),
+        };
     }
 
     fn declaration(&mut self) -> Statement {
         if self.matches(Vec::<TokenType>::from([TokenType::Var])) {
             return self.var_declaration();
+        } else if self.matches(Vec::<TokenType>::from([TokenType::Const])) {
+            return self.const_declaration();
         } else if self.matches(Vec::<TokenType>::from([TokenType::Fun])) {
             return self.function(FunctionType::Function);
         } else if self.matches(Vec::<TokenType>::from([TokenType::Class])) {
             return self.class();
+        } else if self.matches(Vec::<TokenType>::from([TokenType::Trait])) {
+            return self.trait_declaration();
+        } else if self.matches(Vec::<TokenType>::from([TokenType::Enum])) {
+            return self.enum_declaration();
         }
         return self.statement();
     }
@@ -534,14 +880,52 @@ impl Parser {
             );
         }
 
+        let mut traits = Vec::<Token>::new();
+        if self.matches(vec![TokenType::With]) {
+            traits.push(self.consume(TokenType::Identifier, "Expected trait name after with"));
+            while self.matches(vec![TokenType::Comma]) {
+                traits.push(self.consume(TokenType::Identifier, "Expected trait name after ,"));
+            }
+        }
+
         self.consume(
             TokenType::LeftBrace,
             "Expected left brace after class identifier",
         );
 
         let mut methods = Vec::<Statement>::new();
+        let mut statics = Vec::<Statement>::new();
         while !self.check(TokenType::RightBrace) && !self.at_end() {
-            methods.push(self.function(FunctionType::Method));
+            if self.matches(vec![TokenType::Static]) {
+                if self.matches(vec![TokenType::Var]) {
+                    statics.push(self.var_declaration());
+                } else {
+                    self.consume(TokenType::Fun, "expect function or var after static");
+                    statics.push(self.function(FunctionType::Function));
+                }
+            } else if self.check_accessor("get") {
+                self.advance();
+                methods.push(self.function(FunctionType::Getter));
+            } else if self.check_accessor("set") {
+                self.advance();
+                methods.push(self.function(FunctionType::Setter));
+            } else {
+                let method = match self.function(FunctionType::Method) {
+                    Statement::Function {
+                        name: method_name,
+                        params,
+                        body,
+                        functype: _,
+                    } if method_name.lexeme == name.lexeme => Statement::Function {
+                        name: method_name,
+                        params,
+                        body,
+                        functype: FunctionType::Constructor,
+                    },
+                    method => method,
+                };
+                methods.push(method);
+            }
         }
 
         self.consume(
@@ -550,13 +934,97 @@ impl Parser {
         );
 
         return Statement::Class {
-            name: name.clone(),
+            name,
             superclass,
+            traits,
+            methods,
+            statics,
+        };
+    }
+
+    fn enum_declaration(&mut self) -> Statement {
+        let name = self.consume(TokenType::Identifier, "Expected Identifier after enum");
+        self.consume(
+            TokenType::LeftBrace,
+            "Expected left brace after enum identifier",
+        );
+
+        let mut variants = Vec::<(Token, Vec<Token>)>::new();
+        while !self.check(TokenType::RightBrace) && !self.at_end() {
+            let tag = self.consume(TokenType::Identifier, "Expected variant name in enum");
+            let mut fields = Vec::<Token>::new();
+            if self.matches(vec![TokenType::LeftParen]) {
+                fields.push(self.consume(TokenType::Identifier, "Expected field name in variant"));
+                while self.matches(vec![TokenType::Comma]) {
+                    fields.push(self.consume(TokenType::Identifier, "Expected field name in variant"));
+                }
+                self.consume(TokenType::RightParen, "expect ) after variant fields");
+            }
+            variants.push((tag, fields));
+            if !self.matches(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume(
+            TokenType::RightBrace,
+            "Expected right brace after enum body",
+        );
+
+        return Statement::Enum { name, variants };
+    }
+
+    fn trait_declaration(&mut self) -> Statement {
+        let name = self.consume(TokenType::Identifier, "Expected Identifier after trait");
+        self.consume(
+            TokenType::LeftBrace,
+            "Expected left brace after trait identifier",
+        );
+
+        let mut required = Vec::<Token>::new();
+        let mut methods = Vec::<Statement>::new();
+        while !self.check(TokenType::RightBrace) && !self.at_end() {
+            // A signature followed by ; is required, otherwise it is a default method.
+            let checkpoint = self.current;
+            self.consume(TokenType::Fun, "expect function keyword in trait");
+            let method = self.consume(TokenType::Identifier, "expect method name in trait");
+            self.consume(TokenType::LeftParen, "expect ( after method name");
+            while !self.check(TokenType::RightParen) && !self.at_end() {
+                self.advance();
+            }
+            self.consume(TokenType::RightParen, "expect ) after method params");
+            if self.matches(vec![TokenType::SemiColon]) {
+                required.push(method);
+            } else {
+                self.current = checkpoint;
+                methods.push(self.function(FunctionType::Method));
+            }
+        }
+
+        self.consume(
+            TokenType::RightBrace,
+            "Expected right brace after trait body",
+        );
+
+        return Statement::Trait {
+            name,
+            required,
             methods,
         };
     }
 
     fn var_declaration(&mut self) -> Statement {
+        if self.check(TokenType::LeftBracket) || self.check(TokenType::LeftBrace) {
+            let pattern = self.pattern();
+            let equals = self.consume(TokenType::Equal, "expected = after destructuring pattern");
+            let init = self.expression();
+            self.consume(TokenType::SemiColon, "expected semicolon after initlizer");
+            return Statement::Destructure {
+                pattern,
+                equals,
+                init,
+            };
+        }
         let name = self.consume(TokenType::Identifier, "expected variable name");
 
         let mut init = None;
@@ -568,6 +1036,15 @@ impl Parser {
         return Statement::Variable { name: name, init };
     }
 
+    fn const_declaration(&mut self) -> Statement {
+        let name = self.consume(TokenType::Identifier, "expected constant name");
+        self.consume(TokenType::Equal, "expected = after constant name");
+        let init = self.expression();
+
+        self.consume(TokenType::SemiColon, "expected semicolon after constant initlizer");
+        return Statement::Constant { name, init };
+    }
+
     pub fn parse(&mut self) -> Vec<Statement> {
         let mut statements = Vec::<Statement>::new();
         while !self.at_end() {
@@ -0,0 +1,190 @@
+use super::{
+    expressions::{Expression, Object},
+    token::Token,
+};
+
+#[derive(Debug, PartialEq, PartialOrd, Clone)]
+pub enum Pattern {
+    Wildcard,
+    Literal(Object),
+    Binding(Token),
+    // `[a, b, ...rest]` binds the remaining elements to `rest` as a list.
+    List {
+        elements: Vec<Pattern>,
+        rest: Option<Box<Pattern>>,
+    },
+    // Matches instances that have every listed field.
+    Map(Vec<(Token, Pattern)>),
+    Variant {
+        enum_name: Token,
+        tag: Token,
+        fields: Vec<Pattern>,
+    },
+}
+
+#[derive(Debug, PartialEq, PartialOrd, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub arrow: Token,
+    pub body: Expression,
+}
+
+impl Pattern {
+    // Tests `value` against the pattern, collecting the names it binds.
+    // Bindings may be partially filled when the match fails.
+    pub fn matches(&self, value: &Object, bindings: &mut Vec<(Token, Object)>) -> bool {
+        match self {
+            Pattern::Wildcard => return true,
+            Pattern::Literal(literal) => return literal == value,
+            Pattern::Binding(name) => {
+                bindings.push((*name, value.clone()));
+                return true;
+            }
+            Pattern::List { elements, rest } => match value {
+                Object::ListObject(values) => {
+                    let fits = match rest {
+                        Some(_) => values.len() >= elements.len(),
+                        None => values.len() == elements.len(),
+                    };
+                    if !fits
+                        || !elements
+                            .iter()
+                            .zip(values.iter())
+                            .all(|(p, v)| p.matches(v, bindings))
+                    {
+                        return false;
+                    }
+                    return match rest {
+                        Some(p) => p.matches(
+                            &Object::ListObject(values[elements.len()..].to_vec()),
+                            bindings,
+                        ),
+                        None => true,
+                    };
+                }
+                _ => return false,
+            },
+            Pattern::Map(entries) => match value {
+                Object::ClassInstance { memory, .. } => {
+                    for (key, pattern) in entries {
+                        let field = memory.borrow().get(*key);
+                        match field {
+                            Some(v) if pattern.matches(&v, bindings) => (),
+                            _ => return false,
+                        }
+                    }
+                    return true;
+                }
+                _ => return false,
+            },
+            Pattern::Variant {
+                enum_name,
+                tag,
+                fields,
+            } => match value {
+                Object::EnumValue(v) => {
+                    if v.enum_name != enum_name.lexeme || v.tag != tag.lexeme {
+                        return false;
+                    }
+                    if fields.len() != v.values.len() {
+                        panic!(
+                            "Pattern {}.{} expects {} fields but the variant has {} at line {}",
+                            enum_name.lexeme,
+                            tag.lexeme,
+                            fields.len(),
+                            v.values.len(),
+                            tag.line
+                        );
+                    }
+                    return fields
+                        .iter()
+                        .zip(v.values.iter())
+                        .all(|(p, v)| p.matches(v, bindings));
+                }
+                _ => return false,
+            },
+        }
+    }
+
+    pub fn bindings(&self) -> Vec<Token> {
+        let mut names = Vec::<Token>::new();
+        self.collect_bindings(&mut names);
+        return names;
+    }
+
+    fn collect_bindings(&self, names: &mut Vec<Token>) {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) => (),
+            Pattern::Binding(name) => names.push(*name),
+            Pattern::List { elements, rest } => {
+                for p in elements {
+                    p.collect_bindings(names);
+                }
+                if let Some(p) = rest {
+                    p.collect_bindings(names);
+                }
+            }
+            Pattern::Variant { fields, .. } => {
+                for p in fields {
+                    p.collect_bindings(names);
+                }
+            }
+            Pattern::Map(entries) => {
+                for (_, p) in entries {
+                    p.collect_bindings(names);
+                }
+            }
+        }
+    }
+
+    // True when every value matched by `other` is also matched by this
+    // pattern, so an arm using `other` after this one can never run.
+    pub fn covers(&self, other: &Pattern) -> bool {
+        match (self, other) {
+            (Pattern::Wildcard | Pattern::Binding(_), _) => return true,
+            (Pattern::Literal(a), Pattern::Literal(b)) => return a == b,
+            (
+                Pattern::List {
+                    elements: a,
+                    rest: a_rest,
+                },
+                Pattern::List {
+                    elements: b,
+                    rest: b_rest,
+                },
+            ) => {
+                let lengths = match (a_rest, b_rest) {
+                    (Some(r), _) => r.covers(&Pattern::Wildcard) && b.len() >= a.len(),
+                    (None, None) => a.len() == b.len(),
+                    (None, Some(_)) => false,
+                };
+                return lengths && a.iter().zip(b.iter()).all(|(p, q)| p.covers(q));
+            }
+            (Pattern::Map(a), Pattern::Map(b)) => {
+                return a.iter().all(|(key, p)| {
+                    b.iter()
+                        .any(|(other_key, q)| other_key.lexeme == key.lexeme && p.covers(q))
+                })
+            }
+            (
+                Pattern::Variant {
+                    enum_name: a_enum,
+                    tag: a_tag,
+                    fields: a,
+                },
+                Pattern::Variant {
+                    enum_name: b_enum,
+                    tag: b_tag,
+                    fields: b,
+                },
+            ) => {
+                return a_enum.lexeme == b_enum.lexeme
+                    && a_tag.lexeme == b_tag.lexeme
+                    && a.len() == b.len()
+                    && a.iter().zip(b.iter()).all(|(p, q)| p.covers(q))
+            }
+            _ => return false,
+        }
+    }
+}
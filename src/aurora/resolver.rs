@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+
+use super::{
+    expressions::{Expression, FunctionType},
+    interner::Symbol,
+    patterns::Pattern, statements::Statement, token::Token,
+};
+
+// Static pass run between parsing and interpretation. It mirrors the scopes
+// the interpreter creates and reports writes to constant bindings before any
+// code runs. Match arms that can never run are reported as warnings.
+pub struct Resolver {
+    scopes: Vec<HashMap<Symbol, bool>>,
+    // Whether each enclosing function is a generator.
+    functions: Vec<bool>,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        return Resolver {
+            scopes: vec![HashMap::<Symbol, bool>::new()],
+            functions: Vec::<bool>::new(),
+            errors: Vec::<String>::new(),
+            warnings: Vec::<String>::new(),
+        };
+    }
+
+    pub fn resolve(&mut self, statements: &Vec<Statement>) -> Result<(), Vec<String>> {
+        for stmnt in statements {
+            self.statement(stmnt);
+        }
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        return Err(self.errors.clone());
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        return self.warnings.clone();
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::<Symbol, bool>::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors
+            .push(format!("line : {} , {} {}", token.line, message, token.lexeme));
+    }
+
+    // Unlike errors, warnings are not about a single name, so the token only
+    // gives the line.
+    fn warning(&mut self, token: &Token, message: &str) {
+        self.warnings.push(format!("line : {} , {}", token.line, message));
+    }
+
+    fn declare(&mut self, name: &Token, constant: bool) {
+        if self.scopes.last().unwrap().get(&name.lexeme) == Some(&true) {
+            self.error(name, "cannot redeclare constant");
+            return;
+        }
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.lexeme, constant);
+    }
+
+    fn is_constant(&self, name: &Token) -> bool {
+        for scope in self.scopes.iter().rev() {
+            if let Some(constant) = scope.get(&name.lexeme) {
+                return *constant;
+            }
+        }
+        return false;
+    }
+
+    fn function(&mut self, params: &Vec<Token>, body: &Statement, generator: bool) {
+        self.functions.push(generator);
+        self.begin_scope();
+        for param in params {
+            self.declare(param, false);
+        }
+        self.statement(body);
+        self.end_scope();
+        self.functions.pop();
+    }
+
+    fn statement(&mut self, stmnt: &Statement) {
+        match stmnt {
+            Statement::Block { statements } => {
+                self.begin_scope();
+                for s in statements.iter() {
+                    self.statement(s);
+                }
+                self.end_scope();
+            }
+            Statement::Class {
+                name,
+                superclass: _,
+                traits: _,
+                methods,
+                statics,
+            } => {
+                self.declare(name, false);
+                self.begin_scope();
+                for method in methods {
+                    self.statement(method);
+                }
+                for stmnt in statics {
+                    self.statement(stmnt);
+                }
+                self.end_scope();
+            }
+            Statement::Trait {
+                name,
+                required: _,
+                methods,
+            } => {
+                self.declare(name, false);
+                self.begin_scope();
+                for method in methods {
+                    self.statement(method);
+                }
+                self.end_scope();
+            }
+            Statement::Enum { name, variants: _ } => self.declare(name, false),
+            Statement::Expression { expression } => self.expression(expression),
+            Statement::Function {
+                name,
+                params,
+                body,
+                functype,
+            } => {
+                self.declare(name, false);
+                self.function(params, body, *functype == FunctionType::Generator);
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(s) = else_branch {
+                    self.statement(s);
+                }
+            }
+            Statement::Print { expression } => self.expression(expression),
+            Statement::Return { keyword: _, value } => {
+                if let Some(x) = value {
+                    self.expression(x);
+                }
+            }
+            Statement::Variable { name, init } => {
+                if let Some(x) = init {
+                    self.expression(x);
+                }
+                self.declare(name, false);
+            }
+            Statement::Constant { name, init } => {
+                self.expression(init);
+                self.declare(name, true);
+            }
+            Statement::Destructure {
+                pattern,
+                equals: _,
+                init,
+            } => {
+                self.expression(init);
+                for name in pattern.bindings() {
+                    self.declare(&name, false);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Statement::For {
+                init,
+                condition,
+                increment,
+                body,
+            } => {
+                if let Some(s) = &**init {
+                    self.statement(s);
+                }
+                if let Some(x) = condition {
+                    self.expression(x);
+                }
+                if let Some(x) = increment {
+                    self.expression(x);
+                }
+                self.statement(body);
+            }
+            Statement::ForIn {
+                pattern,
+                keyword: _,
+                iterable,
+                body,
+            } => {
+                self.expression(iterable);
+                self.begin_scope();
+                for name in pattern.bindings() {
+                    self.declare(&name, false);
+                }
+                self.statement(body);
+                self.end_scope();
+            }
+            Statement::Yield { keyword, value } => {
+                if self.functions.last() != Some(&true) {
+                    self.error(keyword, "cannot yield outside of a generator");
+                }
+                if let Some(x) = value {
+                    self.expression(x);
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Assign { name, value } => {
+                self.expression(value);
+                if self.is_constant(name) {
+                    self.error(name, "cannot assign to constant");
+                }
+            }
+            Expression::AssignPattern {
+                pattern,
+                equals: _,
+                value,
+            } => {
+                self.expression(value);
+                for name in pattern.bindings() {
+                    if self.is_constant(&name) {
+                        self.error(&name, "cannot assign to constant");
+                    }
+                }
+            }
+            Expression::Binary {
+                left,
+                operator: _,
+                right,
+            } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Call {
+                callee,
+                paren: _,
+                arguments,
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expression::Get { object, .. } => self.expression(object),
+            Expression::Grouping { expression } => self.expression(expression),
+            Expression::Index {
+                object,
+                bracket: _,
+                index,
+            } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expression::Literal { value: _ } => (),
+            Expression::List { elements } => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expression::Match {
+                keyword: _,
+                subject,
+                arms,
+            } => {
+                self.expression(subject);
+                let mut unguarded = Vec::<&Pattern>::new();
+                for arm in arms {
+                    if unguarded.iter().any(|p| p.covers(&arm.pattern)) {
+                        self.warning(&arm.arrow, "unreachable match arm");
+                    }
+                    if arm.guard.is_none() {
+                        unguarded.push(&arm.pattern);
+                    }
+                    self.begin_scope();
+                    for name in arm.pattern.bindings() {
+                        self.declare(&name, false);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    self.expression(&arm.body);
+                    self.end_scope();
+                }
+            }
+            Expression::Logical {
+                left,
+                operator: _,
+                right,
+            } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Set {
+                object,
+                name: _,
+                value,
+                cache: _,
+            } => {
+                self.expression(object);
+                self.expression(value);
+            }
+            Expression::Super {
+                keyword: _,
+                method: _,
+            } => (),
+            Expression::This { keyword: _ } => (),
+            Expression::Unary { operator: _, right } => self.expression(right),
+            Expression::Variable { name: _ } => (),
+        }
+    }
+}
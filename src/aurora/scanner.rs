@@ -1,15 +1,23 @@
 use std::collections::hash_map::HashMap;
 
+use unicode_ident::{is_xid_continue, is_xid_start};
+
 use crate::aurora::token;
 
-use super::token::TokenType;
+use super::{
+    interner::Symbol,
+    token::{Span, TokenType},
+};
 
+// `start` and `current` are byte offsets into `source`, so every slice and
+// span lands on a UTF-8 character boundary.
 pub struct Scanner {
     source: String,
     tokens: Vec<token::Token>,
     start: usize,
     current: usize,
     line: usize,
+    line_start: usize,
     has_error: bool,
     keywords: HashMap<String, TokenType>,
 }
@@ -19,26 +27,36 @@ impl Scanner {
         let mut keywords = HashMap::<String, TokenType>::new();
         keywords.insert(String::from("and"), TokenType::And);
         keywords.insert(String::from("class"), TokenType::Class);
+        keywords.insert(String::from("const"), TokenType::Const);
         keywords.insert(String::from("else"), TokenType::Else);
+        keywords.insert(String::from("enum"), TokenType::Enum);
         keywords.insert(String::from("false"), TokenType::False);
         keywords.insert(String::from("for"), TokenType::For);
         keywords.insert(String::from("function"), TokenType::Fun);
         keywords.insert(String::from("if"), TokenType::If);
+        keywords.insert(String::from("in"), TokenType::In);
+        keywords.insert(String::from("is"), TokenType::Is);
+        keywords.insert(String::from("match"), TokenType::Match);
         keywords.insert(String::from("nil"), TokenType::Nil);
         keywords.insert(String::from("or"), TokenType::Or);
         keywords.insert(String::from("print"), TokenType::Print);
         keywords.insert(String::from("return"), TokenType::Return);
+        keywords.insert(String::from("static"), TokenType::Static);
         keywords.insert(String::from("super"), TokenType::Super);
         keywords.insert(String::from("this"), TokenType::This);
+        keywords.insert(String::from("trait"), TokenType::Trait);
         keywords.insert(String::from("true"), TokenType::True);
         keywords.insert(String::from("var"), TokenType::Var);
         keywords.insert(String::from("while"), TokenType::While);
+        keywords.insert(String::from("with"), TokenType::With);
+        keywords.insert(String::from("yield"), TokenType::Yield);
         return Scanner {
             source: script,
             tokens: Vec::<token::Token>::new(),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
             has_error: false,
             keywords: keywords.clone(),
         };
@@ -49,35 +67,43 @@ impl Scanner {
             self.scan_token();
         }
 
+        // Eof is empty and sits at the end of the source, so errors at the
+        // end don't point at the last token or trailing comments.
+        self.start = self.current;
         self.add_token(TokenType::Eof, String::new());
         return self.tokens.clone();
     }
 
     fn at_end(&self) -> bool {
-        return self.current >= self.source.chars().count() ;
+        return self.current >= self.source.len();
     }
 
     fn advance(&mut self) -> char {
-        self.current += 1;
-        return self
-            .source
-            .chars()
-            .nth(self.current - 1)
-            .unwrap();
+        let c = self.source[self.current..].chars().next().unwrap();
+        self.current += c.len_utf8();
+        return c;
+    }
+
+    fn column(&self) -> usize {
+        return self.source[self.line_start..self.start].chars().count() + 1;
+    }
+
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
     }
 
     fn add_token(&mut self, tokentype: TokenType, literal: String) -> () {
-        let text: String = self
-            .source
-            .chars()
-            .skip(self.start )
-            .take(self.current - self.start)
-            .collect();
+        let text = Symbol::intern(&self.source[self.start..self.current]);
         self.tokens.push(token::Token {
             lexeme: text,
             tokentype: tokentype,
-            literal: literal,
+            literal: Symbol::intern(&literal),
             line: self.line,
+            span: Span {
+                start: self.start,
+                end: self.current,
+            },
         })
     }
 
@@ -88,28 +114,58 @@ impl Scanner {
             ')' => self.add_token(TokenType::RightParen, String::new()),
             '{' => self.add_token(TokenType::LeftBrace, String::new()),
             '}' => self.add_token(TokenType::RightBrace, String::new()),
+            '[' => self.add_token(TokenType::LeftBracket, String::new()),
+            ']' => self.add_token(TokenType::RightBracket, String::new()),
             ',' => self.add_token(TokenType::Comma, String::new()),
-            '.' => self.add_token(TokenType::Dot, String::new()),
+            ':' => self.add_token(TokenType::Colon, String::new()),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    self.add_token(TokenType::DotDotDot, String::new())
+                } else {
+                    self.add_token(TokenType::Dot, String::new())
+                }
+            }
             '-' => self.add_token(TokenType::Minus, String::new()),
             '+' => self.add_token(TokenType::Plus, String::new()),
             ';' => self.add_token(TokenType::SemiColon, String::new()),
             '*' => self.add_token(TokenType::Star, String::new()),
+            '&' => self.add_token(TokenType::Ampersand, String::new()),
+            '|' => self.add_token(TokenType::Pipe, String::new()),
+            '^' => self.add_token(TokenType::Caret, String::new()),
+            '~' => self.add_token(TokenType::Tilde, String::new()),
             '!' => match self.token_match('=') {
                 true => self.add_token(TokenType::BangEqual, String::new()),
                 false => self.add_token(TokenType::Bang, String::new()),
             },
-            '=' => match self.token_match('=') {
-                true => self.add_token(TokenType::EqualEqual, String::new()),
-                false => self.add_token(TokenType::Equal, String::new()),
-            },
-            '<' => match self.token_match('=') {
-                true => self.add_token(TokenType::LessEqual, String::new()),
-                false => self.add_token(TokenType::Less, String::new()),
-            },
-            '>' => match self.token_match('=') {
-                true => self.add_token(TokenType::GreaterEqual, String::new()),
-                false => self.add_token(TokenType::Greater, String::new()),
-            },
+            '=' => {
+                if self.token_match('=') {
+                    self.add_token(TokenType::EqualEqual, String::new())
+                } else if self.token_match('>') {
+                    self.add_token(TokenType::FatArrow, String::new())
+                } else {
+                    self.add_token(TokenType::Equal, String::new())
+                }
+            }
+            '<' => {
+                if self.token_match('=') {
+                    self.add_token(TokenType::LessEqual, String::new())
+                } else if self.token_match('<') {
+                    self.add_token(TokenType::LessLess, String::new())
+                } else {
+                    self.add_token(TokenType::Less, String::new())
+                }
+            }
+            '>' => {
+                if self.token_match('=') {
+                    self.add_token(TokenType::GreaterEqual, String::new())
+                } else if self.token_match('>') {
+                    self.add_token(TokenType::GreaterGreater, String::new())
+                } else {
+                    self.add_token(TokenType::Greater, String::new())
+                }
+            }
             '/' => match self.token_match('/') {
                 true => {
                     while self.peek() != '\n' && !self.at_end() {
@@ -121,7 +177,7 @@ impl Scanner {
                 }
             },
             ' ' | '\r' | '\t' => (),
-            '\n' => self.line += 1,
+            '\n' => self.newline(),
             '"' => self.read_string(),
             _ => {
                 if self.is_digit(c) {
@@ -129,7 +185,12 @@ impl Scanner {
                 }else if self.is_alpha(c) {
                     self.identifier();
                 }else {
-                    println!("line : {} , unexpected character {}", self.line, c);
+                    eprintln!(
+                        "line : {} , column : {} , unexpected character {}",
+                        self.line,
+                        self.column(),
+                        c
+                    );
                     self.has_error = true;
                 }
             }
@@ -140,10 +201,10 @@ impl Scanner {
         if self.at_end() {
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != expected {
+        if self.peek() != expected {
             return false;
         }
-        self.current += 1;
+        self.current += expected.len_utf8();
         return true;
     }
 
@@ -151,31 +212,32 @@ impl Scanner {
         if self.at_end() {
             return '\0';
         }
-        return self.source.chars().nth(self.current ).unwrap();
+        return self.source[self.current..].chars().next().unwrap();
     }
 
     fn read_string(&mut self) {
+        // Strings may span lines, so an unterminated one is reported where
+        // it opens.
+        let (line, column) = (self.line, self.column());
         while self.peek() != '"' && !self.at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
-            }
             self.advance();
+            if self.previous() == '\n' {
+                self.newline();
+            }
         }
 
         if self.at_end() {
-            print!("Unterminated string at {}", self.line);
+            eprintln!(
+                "line : {} , column : {} , unterminated string",
+                line, column
+            );
             self.has_error = true;
             return;
         }
 
         self.advance();
 
-        let text: String = self
-            .source
-            .chars()
-            .skip(self.start + 1)
-            .take(self.current - (self.start + 2))
-            .collect();
+        let text = self.source[self.start + 1..self.current - 1].to_owned();
         self.add_token(TokenType::String, text);
     }
 
@@ -196,43 +258,34 @@ impl Scanner {
         }
         self.add_token(
             TokenType::Number,
-            self.source
-                .chars()
-                .skip(self.start )
-                .take(self.current - self.start)
-                .collect(),
+            self.source[self.start..self.current].to_owned(),
         )
     }
 
     fn peek_next(&self) -> char {
-        if (self.current + 1)  >= self.source.chars().count() {
-            return '\0';
-        }
-        return self
-            .source
-            .chars()
-            .nth(self.current + 1)
-            .unwrap();
+        let mut chars = self.source[self.current..].chars();
+        chars.next();
+        return chars.next().unwrap_or('\0');
+    }
+
+    fn previous(&self) -> char {
+        return self.source[..self.current].chars().next_back().unwrap_or('\0');
     }
 
+    // Identifiers follow Unicode UAX #31: XID_Start (or `_`) followed by XID_Continue.
     fn is_alpha(&self, c: char) -> bool {
-        return (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || (c == '_');
+        return c == '_' || is_xid_start(c);
     }
 
     fn is_alphanumeric(&self, c: char) -> bool {
-        return self.is_alpha(c) || self.is_digit(c);
+        return is_xid_continue(c);
     }
 
     fn identifier(&mut self) -> () {
         while self.is_alphanumeric(self.peek()) {
             self.advance();
         }
-        let text: String = self
-            .source
-            .chars()
-            .skip(self.start )
-            .take(self.current - self.start)
-            .collect();
+        let text = self.source[self.start..self.current].to_owned();
         let tokentype = self.keywords.get(&text).unwrap_or(&TokenType::Identifier);
         self.add_token(tokentype.to_owned(), String::new());
     }
@@ -0,0 +1,236 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt,
+    io::Read,
+    process::{Child, Command, Stdio},
+    rc::Rc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::{
+    environment::Environment,
+    expressions::Object,
+    generators::{self, Generator},
+};
+
+// A spawned generator. Tasks only run once the top-level script has finished
+// and the interpreter hands control to the scheduler.
+#[derive(Clone, PartialEq, PartialOrd)]
+pub struct Task {
+    pub id: usize,
+    pub generator: Rc<RefCell<Generator>>,
+    pub done: bool,
+}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<task {}>", self.id)
+    }
+}
+
+// Values a task yields to suspend itself until something happens.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Wait {
+    Sleep(f64),
+    Process {
+        command: String,
+        result: Rc<RefCell<ProcessResult>>,
+    },
+}
+
+// Filled in once the process has exited; read through `.status` and `.output`.
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
+pub struct ProcessResult {
+    pub status: Option<f64>,
+    pub output: String,
+}
+
+struct Running {
+    child: Child,
+    reader: JoinHandle<String>,
+    result: Rc<RefCell<ProcessResult>>,
+    task: Rc<RefCell<Task>>,
+}
+
+// Cooperative, single-threaded event loop. Ready tasks run in FIFO order and
+// sleeps are ordered by a logical clock, so the interleaving only depends on
+// the script (and on when subprocesses exit), never on how long each step
+// takes to compute.
+pub struct Scheduler {
+    ready: VecDeque<Rc<RefCell<Task>>>,
+    // (wake time in logical ms, sequence number, task)
+    sleeping: Vec<(f64, usize, Rc<RefCell<Task>>)>,
+    running: Vec<Running>,
+    // (task being waited on, waiting task)
+    joining: Vec<(usize, Rc<RefCell<Task>>)>,
+    clock: f64,
+    sequence: usize,
+    started: Instant,
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+impl Scheduler {
+    pub fn new() -> Self {
+        return Scheduler {
+            ready: VecDeque::new(),
+            sleeping: Vec::new(),
+            running: Vec::new(),
+            joining: Vec::new(),
+            clock: 0.0,
+            sequence: 0,
+            started: Instant::now(),
+        };
+    }
+
+    pub fn run(&mut self, env: &mut Environment) {
+        loop {
+            for task in env.take_spawned() {
+                self.ready.push_back(task);
+            }
+            match self.ready.pop_front() {
+                Some(task) => self.step(env, task),
+                None => {
+                    if !self.idle() {
+                        break;
+                    }
+                }
+            }
+        }
+        if !self.joining.is_empty() {
+            panic!(
+                "deadlock: {} task(s) waiting on tasks that never finish",
+                self.joining.len()
+            );
+        }
+    }
+
+    fn step(&mut self, env: &mut Environment, task: Rc<RefCell<Task>>) {
+        let generator = task.borrow().generator.clone();
+        env.set_clock(self.clock);
+        match generators::resume(env, &generator) {
+            None => self.finish(task),
+            Some(Object::Wait(Wait::Sleep(ms))) => {
+                self.sequence += 1;
+                self.sleeping
+                    .push((self.clock + ms.max(0.0), self.sequence, task));
+            }
+            Some(Object::Wait(Wait::Process { command, result })) => {
+                self.start_process(command, result, task)
+            }
+            Some(Object::Task(other)) => {
+                let (id, done) = {
+                    let other = other.borrow();
+                    (other.id, other.done)
+                };
+                match done {
+                    true => self.ready.push_back(task),
+                    false => self.joining.push((id, task)),
+                }
+            }
+            Some(_) => self.ready.push_back(task),
+        }
+    }
+
+    fn finish(&mut self, task: Rc<RefCell<Task>>) {
+        task.borrow_mut().done = true;
+        let id = task.borrow().id;
+        let (woken, waiting): (Vec<_>, Vec<_>) =
+            self.joining.drain(..).partition(|(target, _)| *target == id);
+        self.joining = waiting;
+        for (_, t) in woken {
+            self.ready.push_back(t);
+        }
+    }
+
+    fn start_process(
+        &mut self,
+        command: String,
+        result: Rc<RefCell<ProcessResult>>,
+        task: Rc<RefCell<Task>>,
+    ) {
+        let mut child = match Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => panic!("cannot run {}: {}", command, e),
+        };
+        let mut stdout = child.stdout.take().unwrap();
+        let reader = thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stdout.read_to_string(&mut output);
+            return output;
+        });
+        self.running.push(Running {
+            child,
+            reader,
+            result,
+            task,
+        });
+    }
+
+    // Called when no task is ready. Wakes whatever is due next and returns
+    // false once there is nothing left to wait for.
+    fn idle(&mut self) -> bool {
+        let mut index = 0;
+        let mut woke = false;
+        while index < self.running.len() {
+            match self.running[index].child.try_wait() {
+                Ok(Some(status)) => {
+                    let running = self.running.remove(index);
+                    let output = running.reader.join().unwrap_or_default();
+                    *running.result.borrow_mut() = ProcessResult {
+                        status: status.code().map(|c| c as f64),
+                        output,
+                    };
+                    self.ready.push_back(running.task);
+                    woke = true;
+                }
+                Ok(None) => index += 1,
+                Err(e) => panic!("cannot wait for process: {}", e),
+            }
+        }
+        if woke {
+            return true;
+        }
+
+        let next = self
+            .sleeping
+            .iter()
+            .map(|(wake, _, _)| *wake)
+            .fold(f64::INFINITY, f64::min);
+        if next.is_finite() {
+            let due = self.started + Duration::from_secs_f64(next / 1000.0);
+            let now = Instant::now();
+            if due > now {
+                let pause = due - now;
+                match self.running.is_empty() {
+                    true => thread::sleep(pause),
+                    false => {
+                        thread::sleep(pause.min(POLL_INTERVAL));
+                        return true;
+                    }
+                }
+            }
+            self.clock = next;
+            self.sleeping
+                .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap().then(a.1.cmp(&b.1)));
+            while !self.sleeping.is_empty() && self.sleeping[0].0 <= next {
+                let (_, _, task) = self.sleeping.remove(0);
+                self.ready.push_back(task);
+            }
+            return true;
+        }
+
+        if !self.running.is_empty() {
+            thread::sleep(POLL_INTERVAL);
+            return true;
+        }
+        return false;
+    }
+}
@@ -0,0 +1,1082 @@
+use std::{collections::HashMap, rc::Rc};
+
+use super::{
+    expressions::{Expression, FunctionType, Object},
+    inline_cache::InlineCache,
+    interner::Symbol,
+    patterns::{MatchArm, Pattern},
+    statements::Statement,
+    token::{Span, Token, TokenType},
+};
+
+// Precompiled programs (`.aurc` files): the resolved and optimised AST, so
+// running one skips scanning, parsing and the static checks.
+//
+// Layout, integers little endian:
+//   magic   b"\x7fAURC", whose first byte never starts a source script
+//   version u16, FORMAT_VERSION
+//   check   u64, FNV-1a hash of everything after it
+//   strings varint count, then each as varint length + UTF-8 bytes
+//   program varint count, then each top-level statement
+//
+// Every token is stored with its source line and span, which serve as the
+// line table: runtime errors report the same lines as the original script.
+// Variable-length integers are LEB128.
+const MAGIC: &[u8; 5] = b"\x7fAURC";
+pub const FORMAT_VERSION: u16 = 1;
+const HEADER_LEN: usize = 15;
+
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    return bytes.starts_with(MAGIC);
+}
+
+pub fn write(statements: &Vec<Statement>) -> Vec<u8> {
+    let mut body = Writer {
+        out: Vec::new(),
+        strings: Vec::new(),
+        index: HashMap::new(),
+    };
+    body.varint(statements.len());
+    for stmnt in statements {
+        body.statement(stmnt);
+    }
+
+    let mut payload = Writer {
+        out: Vec::new(),
+        strings: Vec::new(),
+        index: HashMap::new(),
+    };
+    payload.varint(body.strings.len());
+    for symbol in &body.strings {
+        payload.text(symbol.as_str());
+    }
+    payload.out.extend_from_slice(&body.out);
+
+    let mut out = Vec::<u8>::with_capacity(HEADER_LEN + payload.out.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&checksum(&payload.out).to_le_bytes());
+    out.extend_from_slice(&payload.out);
+    return out;
+}
+
+pub fn read(bytes: &[u8]) -> Result<Vec<Statement>, String> {
+    if bytes.len() < HEADER_LEN || !is_compiled(bytes) {
+        return Err("not a compiled aurora program".to_owned());
+    }
+    let version = u16::from_le_bytes([bytes[5], bytes[6]]);
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "compiled with format version {}, this aurora reads version {}; recompile the script",
+            version, FORMAT_VERSION
+        ));
+    }
+    let expected = u64::from_le_bytes(bytes[7..HEADER_LEN].try_into().unwrap());
+    let payload = &bytes[HEADER_LEN..];
+    if checksum(payload) != expected {
+        return Err("checksum mismatch, the compiled program is corrupt".to_owned());
+    }
+
+    // The checksum matched, so anything malformed past this point was
+    // written that way and is a bug in `write`; the reader panics on it.
+    let mut reader = Reader {
+        bytes: payload,
+        position: 0,
+        strings: Vec::new(),
+    };
+    let count = reader.varint();
+    for _ in 0..count {
+        let text = reader.text();
+        reader.strings.push(Symbol::intern(&text));
+    }
+    let count = reader.varint();
+    let mut statements = Vec::<Statement>::with_capacity(count);
+    for _ in 0..count {
+        statements.push(reader.statement());
+    }
+    if reader.position != payload.len() {
+        panic!("trailing bytes after compiled program");
+    }
+    return Ok(statements);
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+// Token types in the order of their tags. Appending is fine; reordering or
+// removing entries needs a new FORMAT_VERSION.
+const TOKEN_TYPES: [TokenType; 59] = [
+    TokenType::LeftParen,
+    TokenType::RightParen,
+    TokenType::LeftBrace,
+    TokenType::RightBrace,
+    TokenType::LeftBracket,
+    TokenType::RightBracket,
+    TokenType::Colon,
+    TokenType::Comma,
+    TokenType::Dot,
+    TokenType::Minus,
+    TokenType::Plus,
+    TokenType::SemiColon,
+    TokenType::Slash,
+    TokenType::Star,
+    TokenType::Ampersand,
+    TokenType::Pipe,
+    TokenType::Caret,
+    TokenType::Tilde,
+    TokenType::Bang,
+    TokenType::BangEqual,
+    TokenType::Equal,
+    TokenType::EqualEqual,
+    TokenType::FatArrow,
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::Less,
+    TokenType::LessEqual,
+    TokenType::LessLess,
+    TokenType::GreaterGreater,
+    TokenType::DotDotDot,
+    TokenType::Identifier,
+    TokenType::String,
+    TokenType::Number,
+    TokenType::And,
+    TokenType::Class,
+    TokenType::Const,
+    TokenType::Else,
+    TokenType::Enum,
+    TokenType::False,
+    TokenType::Fun,
+    TokenType::For,
+    TokenType::If,
+    TokenType::In,
+    TokenType::Is,
+    TokenType::Match,
+    TokenType::Nil,
+    TokenType::Or,
+    TokenType::Print,
+    TokenType::Return,
+    TokenType::Static,
+    TokenType::Super,
+    TokenType::This,
+    TokenType::Trait,
+    TokenType::True,
+    TokenType::Var,
+    TokenType::While,
+    TokenType::With,
+    TokenType::Yield,
+    TokenType::Eof,
+];
+
+const FUNCTION_TYPES: [FunctionType; 6] = [
+    FunctionType::Function,
+    FunctionType::Method,
+    FunctionType::Constructor,
+    FunctionType::Getter,
+    FunctionType::Setter,
+    FunctionType::Generator,
+];
+
+struct Writer {
+    out: Vec<u8>,
+    strings: Vec<Symbol>,
+    index: HashMap<Symbol, usize>,
+}
+
+impl Writer {
+    fn byte(&mut self, value: u8) {
+        self.out.push(value);
+    }
+
+    fn varint(&mut self, value: usize) {
+        let mut value = value as u64;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.out.push(byte);
+                return;
+            }
+            self.out.push(byte | 0x80);
+        }
+    }
+
+    fn text(&mut self, value: &str) {
+        self.varint(value.len());
+        self.out.extend_from_slice(value.as_bytes());
+    }
+
+    fn symbol(&mut self, symbol: Symbol) {
+        let next = self.strings.len();
+        let index = *self.index.entry(symbol).or_insert(next);
+        if index == next {
+            self.strings.push(symbol);
+        }
+        self.varint(index);
+    }
+
+    fn token(&mut self, token: &Token) {
+        let tag = TOKEN_TYPES
+            .iter()
+            .position(|t| *t == token.tokentype)
+            .unwrap();
+        self.byte(tag as u8);
+        self.symbol(token.lexeme);
+        self.symbol(token.literal);
+        self.varint(token.line);
+        self.varint(token.span.start);
+        self.varint(token.span.end);
+    }
+
+    fn tokens(&mut self, tokens: &Vec<Token>) {
+        self.varint(tokens.len());
+        for token in tokens {
+            self.token(token);
+        }
+    }
+
+    fn optional_token(&mut self, token: &Option<Token>) {
+        match token {
+            Some(t) => {
+                self.byte(1);
+                self.token(t);
+            }
+            None => self.byte(0),
+        }
+    }
+
+    fn functype(&mut self, functype: &FunctionType) {
+        let tag = FUNCTION_TYPES.iter().position(|t| t == functype).unwrap();
+        self.byte(tag as u8);
+    }
+
+    // Only values a literal can produce appear in the AST.
+    fn object(&mut self, value: &Object) {
+        match value {
+            Object::NilObject => self.byte(0),
+            Object::BoolObject(b) => {
+                self.byte(1);
+                self.byte(*b as u8);
+            }
+            Object::NumberObject(n) => {
+                self.byte(2);
+                self.out.extend_from_slice(&n.to_le_bytes());
+            }
+            Object::StringObject(s) => {
+                self.byte(3);
+                self.text(s);
+            }
+            other => panic!("cannot compile literal {:?}", other),
+        }
+    }
+
+    fn statements(&mut self, statements: &Vec<Statement>) {
+        self.varint(statements.len());
+        for stmnt in statements {
+            self.statement(stmnt);
+        }
+    }
+
+    fn statement(&mut self, stmnt: &Statement) {
+        match stmnt {
+            Statement::Block { statements } => {
+                self.byte(0);
+                self.statements(statements);
+            }
+            Statement::Class {
+                name,
+                superclass,
+                traits,
+                methods,
+                statics,
+            } => {
+                self.byte(1);
+                self.token(name);
+                self.optional_token(superclass);
+                self.tokens(traits);
+                self.statements(methods);
+                self.statements(statics);
+            }
+            Statement::Trait {
+                name,
+                required,
+                methods,
+            } => {
+                self.byte(2);
+                self.token(name);
+                self.tokens(required);
+                self.statements(methods);
+            }
+            Statement::Enum { name, variants } => {
+                self.byte(3);
+                self.token(name);
+                self.varint(variants.len());
+                for (tag, fields) in variants {
+                    self.token(tag);
+                    self.tokens(fields);
+                }
+            }
+            Statement::Expression { expression } => {
+                self.byte(4);
+                self.expression(expression);
+            }
+            Statement::Function {
+                name,
+                params,
+                body,
+                functype,
+            } => {
+                self.byte(5);
+                self.token(name);
+                self.tokens(params);
+                self.statement(body);
+                self.functype(functype);
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.byte(6);
+                self.expression(condition);
+                self.statement(then_branch);
+                match else_branch {
+                    Some(b) => {
+                        self.byte(1);
+                        self.statement(b);
+                    }
+                    None => self.byte(0),
+                }
+            }
+            Statement::Print { expression } => {
+                self.byte(7);
+                self.expression(expression);
+            }
+            Statement::Return { keyword, value } => {
+                self.byte(8);
+                self.token(keyword);
+                self.optional_expression(value);
+            }
+            Statement::Variable { name, init } => {
+                self.byte(9);
+                self.token(name);
+                self.optional_expression(init);
+            }
+            Statement::Constant { name, init } => {
+                self.byte(10);
+                self.token(name);
+                self.expression(init);
+            }
+            Statement::Destructure {
+                pattern,
+                equals,
+                init,
+            } => {
+                self.byte(11);
+                self.pattern(pattern);
+                self.token(equals);
+                self.expression(init);
+            }
+            Statement::While { condition, body } => {
+                self.byte(12);
+                self.expression(condition);
+                self.statement(body);
+            }
+            Statement::For {
+                init,
+                condition,
+                increment,
+                body,
+            } => {
+                self.byte(13);
+                match &**init {
+                    Some(s) => {
+                        self.byte(1);
+                        self.statement(s);
+                    }
+                    None => self.byte(0),
+                }
+                self.optional_expression(condition);
+                self.optional_expression(increment);
+                self.statement(body);
+            }
+            Statement::ForIn {
+                pattern,
+                keyword,
+                iterable,
+                body,
+            } => {
+                self.byte(14);
+                self.pattern(pattern);
+                self.token(keyword);
+                self.expression(iterable);
+                self.statement(body);
+            }
+            Statement::Yield { keyword, value } => {
+                self.byte(15);
+                self.token(keyword);
+                self.optional_expression(value);
+            }
+        }
+    }
+
+    fn optional_expression(&mut self, expr: &Option<Expression>) {
+        match expr {
+            Some(e) => {
+                self.byte(1);
+                self.expression(e);
+            }
+            None => self.byte(0),
+        }
+    }
+
+    fn expressions(&mut self, exprs: &Vec<Expression>) {
+        self.varint(exprs.len());
+        for expr in exprs {
+            self.expression(expr);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Assign { name, value } => {
+                self.byte(0);
+                self.token(name);
+                self.expression(value);
+            }
+            Expression::AssignPattern {
+                pattern,
+                equals,
+                value,
+            } => {
+                self.byte(1);
+                self.pattern(pattern);
+                self.token(equals);
+                self.expression(value);
+            }
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.byte(2);
+                self.expression(left);
+                self.token(operator);
+                self.expression(right);
+            }
+            Expression::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                self.byte(3);
+                self.expression(callee);
+                self.token(paren);
+                self.expressions(arguments);
+            }
+            Expression::Get {
+                object,
+                name,
+                cache: _,
+            } => {
+                self.byte(4);
+                self.expression(object);
+                self.token(name);
+            }
+            Expression::Grouping { expression } => {
+                self.byte(5);
+                self.expression(expression);
+            }
+            Expression::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                self.byte(6);
+                self.expression(object);
+                self.token(bracket);
+                self.expression(index);
+            }
+            Expression::List { elements } => {
+                self.byte(7);
+                self.expressions(elements);
+            }
+            Expression::Match {
+                keyword,
+                subject,
+                arms,
+            } => {
+                self.byte(8);
+                self.token(keyword);
+                self.expression(subject);
+                self.varint(arms.len());
+                for arm in arms {
+                    self.pattern(&arm.pattern);
+                    self.optional_expression(&arm.guard);
+                    self.token(&arm.arrow);
+                    self.expression(&arm.body);
+                }
+            }
+            Expression::Literal { value } => {
+                self.byte(9);
+                self.object(value);
+            }
+            Expression::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                self.byte(10);
+                self.expression(left);
+                self.token(operator);
+                self.expression(right);
+            }
+            Expression::Set {
+                object,
+                name,
+                value,
+                cache: _,
+            } => {
+                self.byte(11);
+                self.expression(object);
+                self.token(name);
+                self.expression(value);
+            }
+            Expression::Super { keyword, method } => {
+                self.byte(12);
+                self.token(keyword);
+                self.token(method);
+            }
+            Expression::This { keyword } => {
+                self.byte(13);
+                self.token(keyword);
+            }
+            Expression::Unary { operator, right } => {
+                self.byte(14);
+                self.token(operator);
+                self.expression(right);
+            }
+            Expression::Variable { name } => {
+                self.byte(15);
+                self.token(name);
+            }
+        }
+    }
+
+    fn patterns(&mut self, patterns: &Vec<Pattern>) {
+        self.varint(patterns.len());
+        for pattern in patterns {
+            self.pattern(pattern);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Wildcard => self.byte(0),
+            Pattern::Literal(value) => {
+                self.byte(1);
+                self.object(value);
+            }
+            Pattern::Binding(name) => {
+                self.byte(2);
+                self.token(name);
+            }
+            Pattern::List { elements, rest } => {
+                self.byte(3);
+                self.patterns(elements);
+                match rest {
+                    Some(r) => {
+                        self.byte(1);
+                        self.pattern(r);
+                    }
+                    None => self.byte(0),
+                }
+            }
+            Pattern::Map(entries) => {
+                self.byte(4);
+                self.varint(entries.len());
+                for (key, value) in entries {
+                    self.token(key);
+                    self.pattern(value);
+                }
+            }
+            Pattern::Variant {
+                enum_name,
+                tag,
+                fields,
+            } => {
+                self.byte(5);
+                self.token(enum_name);
+                self.token(tag);
+                self.patterns(fields);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    strings: Vec<Symbol>,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> u8 {
+        match self.bytes.get(self.position) {
+            Some(b) => {
+                self.position += 1;
+                return *b;
+            }
+            None => panic!("compiled program ends early"),
+        }
+    }
+
+    fn flag(&mut self) -> bool {
+        return self.byte() != 0;
+    }
+
+    fn varint(&mut self) -> usize {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte();
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return value as usize;
+            }
+            shift += 7;
+        }
+    }
+
+    fn raw(&mut self, len: usize) -> &'a [u8] {
+        if self.position + len > self.bytes.len() {
+            panic!("compiled program ends early");
+        }
+        let slice = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        return slice;
+    }
+
+    fn text(&mut self) -> String {
+        let len = self.varint();
+        match std::str::from_utf8(self.raw(len)) {
+            Ok(s) => return s.to_owned(),
+            Err(e) => panic!("invalid string in compiled program: {}", e),
+        }
+    }
+
+    fn symbol(&mut self) -> Symbol {
+        let index = self.varint();
+        match self.strings.get(index) {
+            Some(symbol) => return *symbol,
+            None => panic!("string {} missing from compiled program", index),
+        }
+    }
+
+    fn token(&mut self) -> Token {
+        let tag = self.byte() as usize;
+        let tokentype = match TOKEN_TYPES.get(tag) {
+            Some(t) => *t,
+            None => panic!("unknown token type {} in compiled program", tag),
+        };
+        return Token {
+            tokentype,
+            lexeme: self.symbol(),
+            literal: self.symbol(),
+            line: self.varint(),
+            span: Span {
+                start: self.varint(),
+                end: self.varint(),
+            },
+        };
+    }
+
+    fn tokens(&mut self) -> Vec<Token> {
+        let count = self.varint();
+        return (0..count).map(|_| self.token()).collect();
+    }
+
+    fn optional_token(&mut self) -> Option<Token> {
+        match self.flag() {
+            true => return Some(self.token()),
+            false => return None,
+        }
+    }
+
+    fn functype(&mut self) -> FunctionType {
+        let tag = self.byte() as usize;
+        match FUNCTION_TYPES.get(tag) {
+            Some(t) => return t.clone(),
+            None => panic!("unknown function type {} in compiled program", tag),
+        }
+    }
+
+    fn object(&mut self) -> Object {
+        match self.byte() {
+            0 => return Object::NilObject,
+            1 => return Object::BoolObject(self.flag()),
+            2 => {
+                let bytes: [u8; 8] = self.raw(8).try_into().unwrap();
+                return Object::NumberObject(f64::from_le_bytes(bytes));
+            }
+            3 => return Object::StringObject(self.text()),
+            tag => panic!("unknown literal tag {} in compiled program", tag),
+        }
+    }
+
+    fn statements(&mut self) -> Vec<Statement> {
+        let count = self.varint();
+        return (0..count).map(|_| self.statement()).collect();
+    }
+
+    fn shared(&mut self) -> Rc<Statement> {
+        return Rc::new(self.statement());
+    }
+
+    fn statement(&mut self) -> Statement {
+        match self.byte() {
+            0 => {
+                return Statement::Block {
+                    statements: Rc::new(self.statements()),
+                }
+            }
+            1 => {
+                return Statement::Class {
+                    name: self.token(),
+                    superclass: self.optional_token(),
+                    traits: self.tokens(),
+                    methods: self.statements(),
+                    statics: self.statements(),
+                }
+            }
+            2 => {
+                return Statement::Trait {
+                    name: self.token(),
+                    required: self.tokens(),
+                    methods: self.statements(),
+                }
+            }
+            3 => {
+                let name = self.token();
+                let count = self.varint();
+                let variants = (0..count).map(|_| (self.token(), self.tokens())).collect();
+                return Statement::Enum { name, variants };
+            }
+            4 => {
+                return Statement::Expression {
+                    expression: self.expression(),
+                }
+            }
+            5 => {
+                return Statement::Function {
+                    name: self.token(),
+                    params: Rc::new(self.tokens()),
+                    body: Rc::new(self.statement()),
+                    functype: self.functype(),
+                }
+            }
+            6 => {
+                let condition = self.expression();
+                let then_branch = self.shared();
+                let else_branch = match self.flag() {
+                    true => Some(self.shared()),
+                    false => None,
+                };
+                return Statement::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                };
+            }
+            7 => {
+                return Statement::Print {
+                    expression: self.expression(),
+                }
+            }
+            8 => {
+                return Statement::Return {
+                    keyword: self.token(),
+                    value: self.optional_expression(),
+                }
+            }
+            9 => {
+                return Statement::Variable {
+                    name: self.token(),
+                    init: self.optional_expression(),
+                }
+            }
+            10 => {
+                return Statement::Constant {
+                    name: self.token(),
+                    init: self.expression(),
+                }
+            }
+            11 => {
+                return Statement::Destructure {
+                    pattern: self.pattern(),
+                    equals: self.token(),
+                    init: self.expression(),
+                }
+            }
+            12 => {
+                return Statement::While {
+                    condition: self.expression(),
+                    body: self.shared(),
+                }
+            }
+            13 => {
+                let init = match self.flag() {
+                    true => Some(self.statement()),
+                    false => None,
+                };
+                return Statement::For {
+                    init: Box::new(init),
+                    condition: self.optional_expression(),
+                    increment: self.optional_expression(),
+                    body: self.shared(),
+                };
+            }
+            14 => {
+                return Statement::ForIn {
+                    pattern: self.pattern(),
+                    keyword: self.token(),
+                    iterable: self.expression(),
+                    body: self.shared(),
+                }
+            }
+            15 => {
+                return Statement::Yield {
+                    keyword: self.token(),
+                    value: self.optional_expression(),
+                }
+            }
+            tag => panic!("unknown statement tag {} in compiled program", tag),
+        }
+    }
+
+    fn optional_expression(&mut self) -> Option<Expression> {
+        match self.flag() {
+            true => return Some(self.expression()),
+            false => return None,
+        }
+    }
+
+    fn expressions(&mut self) -> Vec<Expression> {
+        let count = self.varint();
+        return (0..count).map(|_| self.expression()).collect();
+    }
+
+    fn boxed_expression(&mut self) -> Box<Expression> {
+        return Box::new(self.expression());
+    }
+
+    fn expression(&mut self) -> Expression {
+        match self.byte() {
+            0 => {
+                return Expression::Assign {
+                    name: self.token(),
+                    value: self.boxed_expression(),
+                }
+            }
+            1 => {
+                return Expression::AssignPattern {
+                    pattern: self.pattern(),
+                    equals: self.token(),
+                    value: self.boxed_expression(),
+                }
+            }
+            2 => {
+                return Expression::Binary {
+                    left: self.boxed_expression(),
+                    operator: self.token(),
+                    right: self.boxed_expression(),
+                }
+            }
+            3 => {
+                return Expression::Call {
+                    callee: self.boxed_expression(),
+                    paren: self.token(),
+                    arguments: self.expressions(),
+                }
+            }
+            4 => {
+                return Expression::Get {
+                    object: self.boxed_expression(),
+                    name: self.token(),
+                    cache: InlineCache::new(),
+                }
+            }
+            5 => {
+                return Expression::Grouping {
+                    expression: self.boxed_expression(),
+                }
+            }
+            6 => {
+                return Expression::Index {
+                    object: self.boxed_expression(),
+                    bracket: self.token(),
+                    index: self.boxed_expression(),
+                }
+            }
+            7 => {
+                return Expression::List {
+                    elements: self.expressions(),
+                }
+            }
+            8 => {
+                let keyword = self.token();
+                let subject = self.boxed_expression();
+                let count = self.varint();
+                let arms = (0..count)
+                    .map(|_| MatchArm {
+                        pattern: self.pattern(),
+                        guard: self.optional_expression(),
+                        arrow: self.token(),
+                        body: self.expression(),
+                    })
+                    .collect();
+                return Expression::Match {
+                    keyword,
+                    subject,
+                    arms,
+                };
+            }
+            9 => {
+                return Expression::Literal {
+                    value: self.object(),
+                }
+            }
+            10 => {
+                return Expression::Logical {
+                    left: self.boxed_expression(),
+                    operator: self.token(),
+                    right: self.boxed_expression(),
+                }
+            }
+            11 => {
+                return Expression::Set {
+                    object: self.boxed_expression(),
+                    name: self.token(),
+                    value: self.boxed_expression(),
+                    cache: InlineCache::new(),
+                }
+            }
+            12 => {
+                return Expression::Super {
+                    keyword: self.token(),
+                    method: self.token(),
+                }
+            }
+            13 => {
+                return Expression::This {
+                    keyword: self.token(),
+                }
+            }
+            14 => {
+                return Expression::Unary {
+                    operator: self.token(),
+                    right: self.boxed_expression(),
+                }
+            }
+            15 => {
+                return Expression::Variable {
+                    name: self.token(),
+                }
+            }
+            tag => panic!("unknown expression tag {} in compiled program", tag),
+        }
+    }
+
+    fn patterns(&mut self) -> Vec<Pattern> {
+        let count = self.varint();
+        return (0..count).map(|_| self.pattern()).collect();
+    }
+
+    fn pattern(&mut self) -> Pattern {
+        match self.byte() {
+            0 => return Pattern::Wildcard,
+            1 => return Pattern::Literal(self.object()),
+            2 => return Pattern::Binding(self.token()),
+            3 => {
+                let elements = self.patterns();
+                let rest = match self.flag() {
+                    true => Some(Box::new(self.pattern())),
+                    false => None,
+                };
+                return Pattern::List { elements, rest };
+            }
+            4 => {
+                let count = self.varint();
+                let entries = (0..count).map(|_| (self.token(), self.pattern())).collect();
+                return Pattern::Map(entries);
+            }
+            5 => {
+                return Pattern::Variant {
+                    enum_name: self.token(),
+                    tag: self.token(),
+                    fields: self.patterns(),
+                }
+            }
+            tag => panic!("unknown pattern tag {} in compiled program", tag),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write, FORMAT_VERSION, HEADER_LEN};
+    use crate::aurora::{parser::Parser, scanner::Scanner, statements::Statement};
+
+    fn program() -> Vec<Statement> {
+        let script = "
+            function add(a, b) {
+                return a + b;
+            }
+            var total = 0;
+            for (var i = 0; i < 3; i = i + 1) {
+                total = add(total, i);
+            }
+            print \"total\";
+            print total;
+        ";
+        let tokens = Scanner::new(script.to_string()).scan_tokens();
+        return Parser::new(tokens).parse();
+    }
+
+    #[test]
+    fn round_trip() {
+        let statements = program();
+        assert_eq!(read(&write(&statements)), Ok(statements));
+    }
+
+    #[test]
+    fn flipped_payload_byte_fails_the_checksum() {
+        let mut bytes = write(&program());
+        let middle = HEADER_LEN + (bytes.len() - HEADER_LEN) / 2;
+        bytes[middle] ^= 0x10;
+        assert_eq!(
+            read(&bytes),
+            Err("checksum mismatch, the compiled program is corrupt".to_owned())
+        );
+    }
+
+    #[test]
+    fn truncated_payload_fails_the_checksum() {
+        let bytes = write(&program());
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read(&bytes[..HEADER_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn other_version_is_rejected() {
+        let mut bytes = write(&program());
+        bytes[5..7].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let error = read(&bytes).unwrap_err();
+        assert!(error.contains("recompile the script"), "{}", error);
+    }
+}
@@ -1,34 +1,52 @@
+use std::rc::Rc;
+
 use crate::aurora::expressions::Expression;
 use crate::aurora::token::Token;
 
 use super::{
     environment::{Environment, Memory},
-    expressions::{FunctionType, Object},
+    expressions::{call, call_method, is_tail_callable, lookup_method, FunctionType, Object},
+    gc, generators,
+    patterns::Pattern,
 };
 
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
 pub enum Statement {
+    // Nested statements are behind Rc so that generators can keep them on
+    // their work stack without copying them.
     Block {
-        statements: Vec<Statement>,
+        statements: Rc<Vec<Statement>>,
     },
     Class {
         name: Token,
         superclass: Option<Token>,
+        traits: Vec<Token>,
         methods: Vec<Statement>,
+        statics: Vec<Statement>,
+    },
+    Trait {
+        name: Token,
+        required: Vec<Token>,
+        methods: Vec<Statement>,
+    },
+    Enum {
+        name: Token,
+        variants: Vec<(Token, Vec<Token>)>,
     },
     Expression {
         expression: Expression,
     },
+    // Shared with the function objects made each time this runs.
     Function {
         name: Token,
-        params: Vec<Token>,
-        body: Box<Statement>,
+        params: Rc<Vec<Token>>,
+        body: Rc<Statement>,
         functype: FunctionType,
     },
     If {
         condition: Expression,
-        then_branch: Box<Statement>,
-        else_branch: Option<Box<Statement>>,
+        then_branch: Rc<Statement>,
+        else_branch: Option<Rc<Statement>>,
     },
     Print {
         expression: Expression,
@@ -41,15 +59,34 @@ pub enum Statement {
         name: Token,
         init: Option<Expression>,
     },
+    Constant {
+        name: Token,
+        init: Expression,
+    },
+    Destructure {
+        pattern: Pattern,
+        equals: Token,
+        init: Expression,
+    },
     While {
         condition: Expression,
-        body: Box<Statement>,
+        body: Rc<Statement>,
     },
     For {
         init: Box<Option<Statement>>,
         condition: Option<Expression>,
         increment: Option<Expression>,
-        body: Box<Statement>,
+        body: Rc<Statement>,
+    },
+    ForIn {
+        pattern: Pattern,
+        keyword: Token,
+        iterable: Expression,
+        body: Rc<Statement>,
+    },
+    Yield {
+        keyword: Token,
+        value: Option<Expression>,
     },
 }
 
@@ -57,7 +94,11 @@ impl Statement {
     pub fn evaluate(&self, env: &mut Environment) {
         match &*self {
             Statement::Print { expression: expr } => {
-                println!("{:?}", expr.evaluate(env))
+                let value = expr.evaluate(env);
+                match call_method(env, &value, "__str__", vec![]) {
+                    Some(text) => println!("{:?}", text),
+                    None => println!("{:?}", value),
+                }
             }
             Statement::Expression { expression: expr } => {
                 expr.evaluate(env);
@@ -67,19 +108,25 @@ impl Statement {
                     Some(expr) => expr.evaluate(env),
                     None => super::expressions::Object::NilObject,
                 };
-                let v = match value {
-                    Object::ClassInstance {
-                        name: _,
-                        class,
-                        memory,
-                    } => Object::ClassInstance {
-                        name: n.clone(),
-                        class,
-                        memory,
-                    },
-                    _ => value,
-                };
-                env.define(n.clone(), v);
+                env.define(*n, value);
+            }
+            Statement::Constant { name, init } => {
+                let value = init.evaluate(env);
+                env.define_constant(*name, value);
+            }
+            Statement::Destructure {
+                pattern,
+                equals,
+                init,
+            } => {
+                let value = init.evaluate(env);
+                let mut bindings = Vec::<(Token, Object)>::new();
+                if !pattern.matches(&value, &mut bindings) {
+                    panic!("Cannot destructure {:?} at line {}", value, equals.line);
+                }
+                for (name, v) in bindings {
+                    env.define(name, v);
+                }
             }
             Statement::Block { statements } => {
                 env.stackpush(Memory::new());
@@ -111,6 +158,9 @@ impl Statement {
                 let vals = vec![Object::BoolObject(false), Object::NilObject];
                 while !vals.contains(&condition.evaluate(env)) {
                     body.evaluate(env);
+                    if env.is_set_return() {
+                        break;
+                    }
                 }
             }
             Statement::For {
@@ -131,6 +181,9 @@ impl Statement {
                         let vals = vec![Object::BoolObject(false), Object::NilObject];
                         while !vals.contains(&expr.evaluate(env)) {
                             body.evaluate(env);
+                            if env.is_set_return() {
+                                break;
+                            }
                             match increment {
                                 Some(expr) => {
                                     expr.evaluate(env);
@@ -140,16 +193,37 @@ impl Statement {
                         }
                     }
                     None => {
-                        body.clone().evaluate(env);
+                        body.evaluate(env);
                         match increment {
                             Some(expr) => {
-                                expr.clone().evaluate(env);
+                                expr.evaluate(env);
                             }
                             None => (),
                         }
                     }
                 }
             }
+            Statement::ForIn {
+                pattern,
+                keyword: _,
+                iterable,
+                body,
+            } => {
+                let value = iterable.evaluate(env);
+                let mut cursor = generators::cursor(env, value);
+                while let Some(item) = generators::advance(env, &mut cursor) {
+                    env.stackpush(Memory::new());
+                    generators::bind(env, pattern, item);
+                    body.evaluate(env);
+                    env.stackpop();
+                    if env.is_set_return() {
+                        break;
+                    }
+                }
+            }
+            Statement::Yield { keyword, value: _ } => {
+                panic!("cannot yield outside of a generator {}", keyword);
+            }
             Statement::Function {
                 name,
                 params,
@@ -158,20 +232,25 @@ impl Statement {
             } => {
                 let mut captures = Vec::<(Token, Object)>::new();
                 env.stack_temp_push();
-                for t in params {
-                    env.define(t.clone(), Object::NilObject);
+                for t in params.iter() {
+                    env.define(*t, Object::NilObject);
                 }
-                env.define(name.clone(), Object::NilObject);
+                env.define(*name, Object::NilObject);
                 body.resolve(&mut captures, env);
                 env.stack_temp_pop();
+                let key = match functype {
+                    FunctionType::Setter => name.setter(),
+                    _ => *name,
+                };
                 env.define(
-                    name.clone(),
+                    key,
                     Object::FunctionObject {
-                        name: name.clone(),
+                        name: *name,
                         parameters: params.clone(),
                         body: body.clone(),
-                        captures: captures,
+                        captures: Rc::new(captures),
                         functype: functype.clone(),
+                        home: env.current_scope(),
                     },
                 );
 
@@ -181,6 +260,22 @@ impl Statement {
                     panic!("cannot return without being in function {}", k.clone());
                 }
                 match value {
+                    Some(Expression::Call {
+                        callee,
+                        paren,
+                        arguments,
+                    }) => {
+                        let function = callee.evaluate(env);
+                        if is_tail_callable(env, &function) {
+                            let arguments = arguments.iter().map(|x| x.evaluate(env)).collect();
+                            env.set_call_site(*paren);
+                            env.set_tail_call(function, arguments);
+                            env.set_return(Object::NilObject);
+                        } else {
+                            let object_value = call(env, function, arguments, paren);
+                            env.set_return(object_value);
+                        }
+                    }
                     Some(expr) => {
                         let object_value = expr.evaluate(env);
                         env.set_return(object_value.clone());
@@ -191,7 +286,9 @@ impl Statement {
             Statement::Class {
                 name,
                 superclass,
+                traits,
                 methods,
+                statics,
             } => {
                 let mut captures = Vec::<(Token, Object)>::new();
                 env.stack_temp_push();
@@ -199,35 +296,87 @@ impl Statement {
                     stmnt.resolve(&mut captures, env);
                 }
                 env.stack_temp_pop();
-                let mut class_env: Environment;
-                match superclass {
-                    Some(t) => {
-                        let class_parent = env.get(t.clone());
-                        match class_parent {
-                            Object::Class { name:_, class_env: parent_env } => {
-                                class_env = Environment::new_from_parent(&mut parent_env.clone());
-                            },
-                            _ => panic!("Parent should be a class at {}", name)
-                        }
-                    }
-                    None => class_env = Environment::new()
-                }
-                class_env.define(name.clone(), Object::NilObject);
+                // Each class only holds its own methods; inherited ones are
+                // found by walking the superclass chain.
+                let parent = match superclass {
+                    Some(t) => match env.get(*t) {
+                        class_parent @ Object::Class { .. } => Some(Rc::new(class_parent)),
+                        _ => panic!("Parent should be a class at {}", name),
+                    },
+                    None => None,
+                };
+                let mut class_env = Environment::new();
+                class_env.define(*name, Object::NilObject);
                 for capture in captures {
                     class_env.inject(capture.0, capture.1);
                 }
+                let mut required = Vec::<(Token, Token)>::new();
+                for t in traits {
+                    match env.get(*t) {
+                        Object::Trait {
+                            name: trait_name,
+                            required: trait_required,
+                            methods: defaults,
+                        } => {
+                            for method in defaults.iter() {
+                                method.evaluate(&mut class_env);
+                            }
+                            for method in trait_required.iter() {
+                                required.push((*method, trait_name));
+                            }
+                        }
+                        _ => panic!("{} is not a trait at {}", t.lexeme, name),
+                    }
+                }
                 for method in methods {
                     method.evaluate(&mut class_env);
                 }
+                env.stackpush(Memory::new());
+                for stmnt in statics {
+                    stmnt.evaluate(env);
+                }
+                let statics = env.stackpop().unwrap();
+                let class = Object::Class {
+                    name: *name,
+                    class_env: Rc::new(class_env),
+                    statics: gc::alloc_memory(env, statics),
+                    superclass: parent,
+                };
+                for (method, trait_name) in required {
+                    match lookup_method(&class, method) {
+                        Some((Object::FunctionObject { .. }, _)) => (),
+                        _ => panic!(
+                            "Class {} is missing method {} required by trait {} at line {}",
+                            name.lexeme, method.lexeme, trait_name.lexeme, name.line
+                        ),
+                    }
+                }
+                env.define(*name, class);
+            }
+
+            Statement::Trait {
+                name,
+                required,
+                methods,
+            } => {
                 env.define(
-                    name.clone(),
-                    Object::Class {
-                        name: name.clone(),
-                        class_env: Box::new(class_env),
+                    *name,
+                    Object::Trait {
+                        name: *name,
+                        required: Rc::new(required.clone()),
+                        methods: Rc::new(methods.clone()),
+                    },
+                );
+            }
+            Statement::Enum { name, variants } => {
+                env.define(
+                    *name,
+                    Object::Enum {
+                        name: *name,
+                        variants: Rc::new(variants.clone()),
                     },
                 );
             }
-
             _ => panic!("Invalid Statement"),
         }
     }
@@ -235,15 +384,23 @@ impl Statement {
     pub fn resolve(&self, captures: &mut Vec<(Token, Object)>, env: &mut Environment) {
         match self {
             Statement::Block { statements } => {
-                for s in statements {
+                for s in statements.iter() {
                     s.resolve(captures, env);
                 }
             }
             Statement::Class {
                 name,
                 superclass: _,
+                traits: _,
                 methods: _,
-            } => env.define(name.clone(), Object::NilObject),
+                statics: _,
+            } => env.define(*name, Object::NilObject),
+            Statement::Trait {
+                name,
+                required: _,
+                methods: _,
+            } => env.define(*name, Object::NilObject),
+            Statement::Enum { name, variants: _ } => env.define(*name, Object::NilObject),
             Statement::Expression { expression } => expression.resolve(captures, env),
             Statement::Function {
                 name,
@@ -251,9 +408,9 @@ impl Statement {
                 body,
                 functype: _,
             } => {
-                env.define(name.clone(), Object::NilObject);
-                for t in params {
-                    env.define(t.clone(), Object::NilObject);
+                env.define(*name, Object::NilObject);
+                for t in params.iter() {
+                    env.define(*t, Object::NilObject);
                 }
                 body.resolve(captures, env);
             }
@@ -275,12 +432,26 @@ impl Statement {
                 None => (),
             },
             Statement::Variable { name, init } => {
-                env.define(name.clone(), Object::NilObject);
+                env.define(*name, Object::NilObject);
                 match init {
                     Some(x) => x.resolve(captures, env),
                     None => (),
                 }
             }
+            Statement::Constant { name, init } => {
+                env.define(*name, Object::NilObject);
+                init.resolve(captures, env);
+            }
+            Statement::Destructure {
+                pattern,
+                equals: _,
+                init,
+            } => {
+                for name in pattern.bindings() {
+                    env.define(name, Object::NilObject);
+                }
+                init.resolve(captures, env);
+            }
             Statement::While { condition, body } => {
                 condition.resolve(captures, env);
                 body.resolve(captures, env);
@@ -305,6 +476,23 @@ impl Statement {
                 }
                 body.resolve(captures, env);
             }
+            Statement::ForIn {
+                pattern,
+                keyword: _,
+                iterable,
+                body,
+            } => {
+                iterable.resolve(captures, env);
+                for name in pattern.bindings() {
+                    env.define(name, Object::NilObject);
+                }
+                body.resolve(captures, env);
+            }
+            Statement::Yield { keyword: _, value } => {
+                if let Some(x) = value {
+                    x.resolve(captures, env);
+                }
+            }
         }
     }
 }
@@ -1,23 +1,29 @@
 use std::fmt;
 
+use super::interner::Symbol;
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Copy)]
 pub enum TokenType{
   // Single-character tokens.
   LeftParen, RightParen, LeftBrace, RightBrace,
-  Comma, Dot, Minus, Plus, SemiColon, Slash, Star,
+  LeftBracket, RightBracket,
+  Colon, Comma, Dot, Minus, Plus, SemiColon, Slash, Star,
+  Ampersand, Pipe, Caret, Tilde,
 
   // One or two character tokens.
   Bang, BangEqual,
-  Equal, EqualEqual,
+  Equal, EqualEqual, FatArrow,
   Greater, GreaterEqual,
   Less, LessEqual,
+  LessLess, GreaterGreater,
+  DotDotDot,
 
   // Literals.
   Identifier, String, Number,
 
   // Keywords.
-  And, Class, Else, False, Fun, For, If, Nil, Or,
-  Print, Return, Super, This, True, Var, While,
+  And, Class, Const, Else, Enum, False, Fun, For, If, In, Is, Match, Nil, Or,
+  Print, Return, Static, Super, This, Trait, True, Var, While, With, Yield,
 
   Eof
 }
@@ -28,16 +34,57 @@ impl fmt::Display for TokenType {
     }    
 }
 
-#[derive(Debug,Clone, PartialEq, PartialOrd)]
+// Byte offsets of a token in the source, end exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct Token {
-    pub lexeme: String,
+    pub lexeme: Symbol,
     pub tokentype: TokenType,
-    pub literal: String,
+    pub literal: Symbol,
     pub line: usize,
+    pub span: Span,
+}
+
+impl Token {
+    // Identifier for bindings the interpreter creates itself.
+    pub fn internal(lexeme: &str) -> Token {
+        return Token {
+            lexeme: Symbol::intern(lexeme),
+            tokentype: TokenType::Identifier,
+            literal: Symbol::default(),
+            line: 0,
+            span: Span::default(),
+        };
+    }
+
+    // Like `internal`, for names only known at run time: None when no
+    // binding can have the name, without interning it.
+    pub fn lookup(lexeme: &str) -> Option<Token> {
+        return Some(Token {
+            lexeme: Symbol::lookup(lexeme)?,
+            tokentype: TokenType::Identifier,
+            literal: Symbol::default(),
+            line: 0,
+            span: Span::default(),
+        });
+    }
+
+    // Setters share their property's name, so they are stored under a key
+    // that can never be written as an identifier.
+    pub fn setter(&self) -> Token {
+        let mut token = *self;
+        token.lexeme = Symbol::intern(&format!("set {}", self.lexeme));
+        return token;
+    }
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Token Type: {},  Lexeme: {}, String: {}, Line: {}", self.tokentype, self.lexeme, self.literal, self.line)
+        write!(f, "Token Type: {},  Lexeme: {}, String: {}, Line: {}, Span: {}..{}", self.tokentype, self.lexeme, self.literal, self.line, self.span.start, self.span.end)
     }
 }
\ No newline at end of file
@@ -1,27 +1,106 @@
-use std::{env, process::exit, fs, io::{self, Write, stdout}};
+use std::{env, process::exit, fs, io::{self, Write, stdout}, path::Path};
 mod aurora;
 
+use aurora::dump;
+use aurora::optimizer;
 use aurora::parser;
+use aurora::serialize;
+use aurora::environment::{DEFAULT_MAX_DEPTH, STACK_PER_CALL};
 use aurora::interpreter::Interpreter;
+use aurora::resolver::Resolver;
+use aurora::statements::Statement;
 
+const USAGE: &str = "Usage: aurora [-O0|-O1] [--dump-tokens] [--dump-ast] [--max-depth N] [--gc-stress] [script | program.aurc]
+       aurora compile [-O0|-O1] script [-o program.aurc]";
+
+#[derive(Clone, Default)]
+struct Options {
+    script: Option<String>,
+    max_depth: Option<usize>,
+    gc_stress: bool,
+    // 0 runs the AST as parsed, 1 runs the optimisation pass first.
+    opt_level: usize,
+    // Print the tokens or the syntax tree instead of running the script.
+    dump_tokens: bool,
+    dump_ast: bool,
+    // `aurora compile`: write the program to `output` instead of running it.
+    compile: bool,
+    output: Option<String>,
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        opt_level: 1,
+        ..Options::default()
+    };
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(|a| a.as_str()) == Some("compile") {
+        args.next();
+        options.compile = true;
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-depth" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => options.max_depth = Some(n),
+                None => {
+                    println!("--max-depth expects a number\n{USAGE}");
+                    exit(1);
+                }
+            },
+            "--gc-stress" => options.gc_stress = true,
+            "-O0" => options.opt_level = 0,
+            "-O1" => options.opt_level = 1,
+            "--dump-tokens" => options.dump_tokens = true,
+            "--dump-ast" => options.dump_ast = true,
+            "--dump-bytecode" => {
+                println!("--dump-bytecode: aurora has no bytecode compiler, scripts run as a syntax tree; use --dump-ast");
+                exit(1);
+            }
+            "-o" if options.compile => match args.next() {
+                Some(path) => options.output = Some(path),
+                None => {
+                    println!("-o expects a file name\n{USAGE}");
+                    exit(1);
+                }
+            },
+            _ if arg.starts_with("-") || options.script.is_some() => {
+                println!("{USAGE}");
+                exit(1);
+            }
+            _ => options.script = Some(arg),
+        }
+    }
+    if options.compile && options.script.is_none() {
+        println!("{USAGE}");
+        exit(1);
+    }
+    return options;
+}
 
 fn main() {
     const N: usize = 1_000_000;
+    let options = parse_args();
+
+    let max_depth = options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+    let stack_size = max_depth.saturating_mul(STACK_PER_CALL).max(1024 * N);
 
-    std::thread::Builder::new()
-        .stack_size(1024 * N)
-        .spawn(||{
-    match  env::args().len() {
-        1 => run_prompt(),
-        2 => run_file(env::args().nth(1).unwrap()).unwrap(),
-        _ => {
-            println!("Usage: aurora [script]");
+    let thread = std::thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move ||{
+    match options.script.clone() {
+        None => run_prompt(&options),
+        Some(path) => run_file(path, &options).unwrap(),
+    }});
+    match thread {
+        Ok(thread) => thread.join().unwrap(),
+        Err(_) => {
+            println!("cannot reserve enough stack for a call depth of {max_depth}, try a smaller --max-depth");
             exit(1);
         }
-    }}).unwrap().join().unwrap();
+    }
 }
 
-fn run_prompt() -> () {
+fn run_prompt(options: &Options) -> () {
     let mut page = String::new();
     println!("Welcome to aurora interpreter, write your script below :");
     loop {
@@ -30,30 +109,114 @@ fn run_prompt() -> () {
         let mut line = String::new();
         std::io::stdin().read_line(&mut line).unwrap();
         page.push_str(&line);
-        println!("{line}"); 
+        println!("{line}");
         println!("{page}");
-        run(line);
+        run(line, options);
     }
 
 }
 
-fn run_file(path: String) -> Result<(), io::Error> {
-    let script = fs::read_to_string(path)?;
-    run(script);
+fn run_file(path: String, options: &Options) -> Result<(), io::Error> {
+    let bytes = fs::read(&path)?;
+    if serialize::is_compiled(&bytes) {
+        let stmt = match serialize::read(&bytes) {
+            Ok(stmt) => stmt,
+            Err(error) => {
+                println!("{path}: {error}");
+                exit(65);
+            }
+        };
+        if options.compile {
+            println!("{path} is already compiled");
+            exit(1);
+        }
+        if options.dump_tokens {
+            println!("{path}: compiled programs keep no tokens, dump the script instead");
+            exit(1);
+        }
+        execute(stmt, options);
+        return Ok(());
+    }
+    let script = match String::from_utf8(bytes) {
+        Ok(script) => script,
+        Err(error) => return Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+    };
+    if options.compile {
+        let Some(stmt) = compile(script, options) else {
+            exit(65);
+        };
+        let output = match &options.output {
+            Some(output) => output.clone(),
+            None => Path::new(&path).with_extension("aurc").to_string_lossy().into_owned(),
+        };
+        fs::write(output, serialize::write(&stmt))?;
+        return Ok(());
+    }
+    run(script, options);
     return Ok(());
 }
 
-fn run(script: String) -> () {
+fn run(script: String, options: &Options) -> () {
+    if options.dump_tokens {
+        // Only the scanner runs, so scripts that don't parse can be dumped.
+        let tokens = aurora::scanner::Scanner::new(script.clone()).scan_tokens();
+        print!("{}", dump::tokens(&tokens));
+        if !options.dump_ast {
+            return;
+        }
+    }
+    match compile(script, options) {
+        Some(stmt) => execute(stmt, options),
+        // The prompt carries on after an error, scripts stop.
+        None => {
+            if options.script.is_some() {
+                exit(65);
+            }
+        }
+    }
+}
+
+// Scans, parses, checks and optimises a script. Returns None when the
+// script has errors, after printing them to stderr.
+fn compile(script: String, options: &Options) -> Option<Vec<Statement>> {
     let mut scanner = aurora::scanner::Scanner::new(script);
     let tokens = scanner.scan_tokens();
-    let mut parser = parser::Parser::new(tokens.clone());
+    let mut parser = parser::Parser::new(tokens);
     let stmt = parser.parse();
-    let mut inter = Interpreter::new(stmt.clone());
-    
-    // for token in tokens.iter() {
-    //     println!("{token}",);
-    // }
-    // println!("{:?}", stmt);
-    inter.interpret();
+    let mut resolver = Resolver::new();
+    let resolved = resolver.resolve(&stmt);
+    for warning in resolver.warnings() {
+        eprintln!("warning: {warning}");
+    }
+    if let Err(errors) = resolved {
+        for error in errors {
+            eprintln!("{error}");
+        }
+        return None;
+    }
+    match options.opt_level {
+        0 => return Some(stmt),
+        _ => return Some(optimizer::optimize(stmt)),
+    }
 }
 
+fn execute(stmt: Vec<Statement>, options: &Options) {
+    if options.dump_ast {
+        print!("{}", dump::ast(&stmt));
+    }
+    if options.dump_tokens || options.dump_ast {
+        return;
+    }
+    let mut inter = Interpreter::new(stmt);
+    if let Some(max_depth) = options.max_depth {
+        inter.set_max_depth(max_depth);
+    }
+    inter.set_gc_stress(options.gc_stress);
+
+    if let Err(error) = inter.try_interpret() {
+        println!("{error}");
+        if options.script.is_some() {
+            exit(70);
+        }
+    }
+}